@@ -0,0 +1,41 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use r_maze::geometry::*;
+use r_maze::light::*;
+use r_maze::mazes::*;
+use r_maze::mazes::maze_gen::Params;
+use r_maze::solver;
+
+/// Seed for the maze, so that every run times the same one
+const SEED: u32 = 60;
+
+/// Time the lighting for a frame, with each light model and a few
+/// draw distances, on a large seeded maze with lamps scattered
+/// about.  The flashlight walks the route to the goal as it goes.
+fn lighting(c: &mut Criterion) {
+    let mut maze = maze_gen::generate_locked(&Params{size: 60, seed: Some(SEED), ..Params::default()});
+    let mut floor: Vec<Loc> = maze.map.keys().cloned().collect();
+    floor.sort_by_key(|l| (l.y, l.x));
+    for loc in floor.iter().step_by(15) {
+        maze.map.insert(*loc, Tile::Lamp);
+    }
+    let route = solver::solve(&maze).unwrap_or_else(|| vec![maze.start]);
+
+    for model in [Model::Rays, Model::Shadowcast].iter() {
+        for radius in [10, 20, 40].iter() {
+            let mut lighting = Lighting::new(&maze, *radius);
+            let mut i = 0;
+            c.bench_function(&format!("{:?}, draw distance {}", model, radius), |b| b.iter(|| {
+                let loc = route[i % route.len()];
+                let dir = Dir::north().turn(&Angle::a90().times(i as Int));
+                lighting.reset(loc);
+                lighting.illuminate(&maze, *model, &Source::mk_source(loc, dir, 100.0), 1.0);
+                lighting.illuminate_lamps(&maze, *model);
+                i += 1;
+            }));
+        }
+    }
+}
+
+criterion_group!(benches, lighting);
+criterion_main!(benches);
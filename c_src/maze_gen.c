@@ -306,7 +306,7 @@ int iterate_paths (struct mazetile *tiles, int size, struct path_head *last, str
 
 
 
-struct maze *generate_maze ( int size, int twisty, int swirly, int branchy )
+struct maze *generate_maze ( int size, int twisty, int swirly, int branchy, unsigned int seed )
 {
     int x;
     int y;
@@ -346,8 +346,9 @@ struct maze *generate_maze ( int size, int twisty, int swirly, int branchy )
         }
     }
 
-    // Initialize random generator
-    srand ( time ( NULL ) );
+    // Initialize random generator.  The same seed always makes the
+    // same maze.
+    srand ( seed );
 
     x = ( int ) ( rand () / ( double ) RAND_MAX * ( ( double ) size - 6 ) ) + 3;
     y = ( int ) ( rand () / ( double ) RAND_MAX * ( ( double ) size - 6 ) ) + 3;
@@ -22,6 +22,6 @@
 
 #include "internals.h"
 
-struct maze *generate_maze ( int size, int twisty, int swirly, int branchy );
+struct maze *generate_maze ( int size, int twisty, int swirly, int branchy, unsigned int seed );
 
 #endif
@@ -0,0 +1,126 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+/// How often the flashlight gets a chance to flicker, in seconds.
+/// Flickering steps at this fixed rate rather than once per frame, so
+/// that a given seed flickers the same way at any frame rate.
+const FLICKER_TICK: f64 = 0.05;
+
+/// Load on the battery with the flashlight switched off.  It still
+/// runs down, just much more slowly.
+pub const OFF_LOAD: f64 = 0.15;
+
+/// How the flashlight's light output follows the battery's charge
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Curve {
+    /// Light output drops in step with the charge
+    Linear,
+    /// Light output follows the charge raised to some power.  Below
+    /// 1, the light holds up well until the battery is nearly flat
+    /// and then fades fast.  Above 1, it starts fading right away.
+    Power(f64),
+}
+
+/// Settings for how a battery runs down.  Charges are percentages.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct BatteryConfig {
+    /// Charge lost per second while walking
+    pub drain_moving: f64,
+    /// Charge lost per second while standing still
+    pub drain_still: f64,
+    pub curve: Curve,
+    /// Charge below which the light starts to flicker
+    pub flicker_below: f64,
+    /// Charge at which the light goes out for good
+    pub empty: f64,
+    /// Charge a new battery comes with
+    pub start: f64,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> BatteryConfig {
+        BatteryConfig{
+            drain_moving: 4.0,
+            drain_still: 3.0,
+            curve: Curve::Linear,
+            flicker_below: 25.0,
+            empty: 5.0,
+            start: 100.0,
+        }
+    }
+}
+
+/// The flashlight's battery
+#[derive(Clone, Debug)]
+pub struct Battery {
+    pub config: BatteryConfig,
+    charge: f64,
+    /// Fraction of normal output the light is giving off right now
+    flicker: f64,
+    /// Time since the last flicker tick
+    since_tick: f64,
+    rng: StdRng,
+}
+
+impl Battery {
+    /// Make a new battery, with the starting charge its config gives.
+    /// The seed decides how it will flicker once it runs low.
+    pub fn new(config: BatteryConfig, seed: u64) -> Battery {
+        Battery{
+            config,
+            charge: config.start,
+            flicker: 1.0,
+            since_tick: 0.0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+    /// Run the battery down for some time, spent either walking or
+    /// standing still.  The load scales how fast it drains, with 1
+    /// being the flashlight's classic beam.
+    pub fn update(&mut self, dt: f64, moving: bool, load: f64) {
+        let drain = if moving {
+            self.config.drain_moving
+        } else {
+            self.config.drain_still
+        };
+        self.charge = (self.charge - dt * drain * load).max(0.0);
+
+        self.since_tick += dt;
+        while self.since_tick >= FLICKER_TICK {
+            self.since_tick -= FLICKER_TICK;
+            self.flicker = self.roll_flicker();
+        }
+    }
+    /// The lower the charge, the more often the light dips
+    fn roll_flicker(&mut self) -> f64 {
+        let low = (self.config.flicker_below - self.charge) / self.config.flicker_below;
+        if low > 0.0 && self.rng.gen::<f64>() < low * 0.5 {
+            self.rng.gen_range(0.2, 0.7)
+        } else {
+            1.0
+        }
+    }
+    pub fn charge(&self) -> f64 {
+        self.charge
+    }
+    /// Put the battery back at a charge it had before, such as in a
+    /// saved game
+    pub fn set_charge(&mut self, charge: f64) {
+        self.charge = charge.clamp(0.0, 100.0);
+    }
+    /// Light output as a percentage of a fresh battery's
+    pub fn power(&self) -> f64 {
+        let steady = match self.config.curve {
+            Curve::Linear => self.charge,
+            Curve::Power(p) => 100.0 * (self.charge / 100.0).powf(p),
+        };
+        steady * self.flicker
+    }
+    /// How full the battery is, from 0 when it is empty up to 1
+    pub fn level(&self) -> f64 {
+        ((self.charge - self.config.empty) / (100.0 - self.config.empty)).max(0.0)
+    }
+    pub fn is_dead(&self) -> bool {
+        self.charge <= self.config.empty
+    }
+}
@@ -0,0 +1,43 @@
+use crate::bots;
+use crate::game::*;
+use crate::levels::*;
+
+/// Seed for the games the bots play, so every bot sees the same mazes
+const BOT_SEED: u64 = 1;
+
+/// Steps a bot gets to finish a level before it's given up on: ten
+/// minutes of play
+const BOT_STEPS: u64 = 36000;
+
+/// Have each bot play each level of the pack on its own, and say how
+/// long it took and how far it walked.  How the bots fare against
+/// each other is a rough measure of how hard a maze is.
+pub fn bots(pack: &Pack) {
+    for level in 0..pack.levels.len() {
+        let single = Pack{name: pack.name.clone(), levels: vec![pack.levels[level].clone()]};
+        println!("{}", pack.levels[level].name);
+        for name in bots::BOT_NAMES.iter() {
+            let mut game = Game::new(single.clone(), BOT_SEED);
+            let mut bot = match bots::bot(name) {
+                Some(b) => b,
+                None => continue,
+            };
+            while game.state != State::Won && game.state != State::Lost && game.steps < BOT_STEPS {
+                for (action, state) in bot.actions(&game) {
+                    game.act(action, state);
+                }
+                game.step();
+            }
+            let result = match game.state {
+                State::Won => "won",
+                State::Lost => "lost",
+                _ => "gave up",
+            };
+            let run = game.run();
+            println!(
+                "  {:8} {:8} {:6.1}s {:5} tiles {:4.0}% explored",
+                name, result, run.time, run.tiles, run.explored * 100.0,
+            );
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use piston_window::ButtonState;
+
+use std::collections::{HashMap,HashSet};
+
+use crate::geometry::*;
+use crate::controls::*;
+use crate::controller::*;
+use crate::game::*;
+use crate::solver;
+
+/// Steps a bot spends on each menu screen before confirming, so
+/// that anyone watching can see it
+const MENU_STEPS: u64 = 120;
+
+/// A way of picking which way to walk on from each tile
+pub trait Strategy {
+    /// Choose a direction to walk from the tile the player has just
+    /// come to, or None to stay put
+    fn choose(&mut self, game: &Game, here: Loc) -> Option<Dir>;
+    /// Forget everything, ready for a new maze
+    fn reset(&mut self);
+}
+
+/// Plays the game with a strategy, walking tile by tile and taking
+/// the first choice on every menu
+pub struct Bot<S> {
+    strategy: S,
+    /// The move being held down
+    held: Option<Action>,
+    /// The tile the last choice was made on
+    chosen_at: Option<Loc>,
+    /// Whether Confirm was pressed last step, and needs letting go
+    confirming: bool,
+    /// Steps spent on the current menu screen
+    waited: u64,
+}
+
+impl<S: Strategy> Bot<S> {
+    pub fn new(strategy: S) -> Bot<S> {
+        Bot{strategy, held: None, chosen_at: None, confirming: false, waited: 0}
+    }
+    /// Switch to holding down a different move, if any
+    fn hold(&mut self, action: Option<Action>, actions: &mut Vec<(Action, ButtonState)>) {
+        if action == self.held {
+            return;
+        }
+        if let Some(a) = self.held {
+            actions.push((a, ButtonState::Release));
+        }
+        if let Some(a) = action {
+            actions.push((a, ButtonState::Press));
+        }
+        self.held = action;
+    }
+}
+
+fn move_action(dir: Dir) -> Option<Action> {
+    match dir.offset() {
+        (0, -1) => Some(Action::MoveNorth),
+        (0, 1) => Some(Action::MoveSouth),
+        (-1, 0) => Some(Action::MoveWest),
+        (1, 0) => Some(Action::MoveEast),
+        _ => None,
+    }
+}
+
+impl<S: Strategy> Controller for Bot<S> {
+    fn actions(&mut self, game: &Game) -> Vec<(Action, ButtonState)> {
+        let mut actions = Vec::new();
+        match game.state {
+            State::Playing => match game.loc {
+                LocMode::Complete(here) if self.chosen_at != Some(here) => {
+                    self.chosen_at = Some(here);
+                    let dir = self.strategy.choose(game, here);
+                    self.hold(dir.and_then(move_action), &mut actions);
+                },
+                LocMode::Free(_) => actions.push((Action::ToggleFree, ButtonState::Press)),
+                _ => (),
+            },
+            State::Quit => (),
+            _ => {
+                // Between mazes, so start afresh
+                self.hold(None, &mut actions);
+                self.chosen_at = None;
+                self.strategy.reset();
+                self.waited += 1;
+                if !self.confirming && self.waited >= MENU_STEPS {
+                    actions.push((Action::Confirm, ButtonState::Press));
+                    self.confirming = true;
+                    self.waited = 0;
+                    return actions;
+                }
+            },
+        }
+        if self.confirming {
+            actions.push((Action::Confirm, ButtonState::Release));
+            self.confirming = false;
+        }
+        actions
+    }
+}
+
+/// Straight directions, in the order bots try them
+fn open_dirs(game: &Game) -> Vec<Dir> {
+    solver::walk_dirs().iter().cloned().filter(|d| game.can_move(*d)).collect()
+}
+
+/// Which hand a wall follower keeps on the wall
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+/// Keeps one hand on the wall, turning that way whenever it can.
+/// Gets out of any maze without loops, but can go round in circles
+/// forever in one with them.
+#[derive(Debug)]
+pub struct WallFollower {
+    hand: Hand,
+    facing: Option<Dir>,
+}
+
+impl WallFollower {
+    pub fn new(hand: Hand) -> WallFollower {
+        WallFollower{hand, facing: None}
+    }
+}
+
+impl Strategy for WallFollower {
+    fn choose(&mut self, game: &Game, _here: Loc) -> Option<Dir> {
+        let facing = self.facing.unwrap_or(game.dir);
+        let turn = match self.hand {
+            Hand::Left => Angle::a90().reverse(),
+            Hand::Right => Angle::a90(),
+        };
+        // Toward the wall, straight on, away from it, then back
+        let tries = [
+            facing.turn(&turn),
+            facing,
+            facing.turn(&turn.reverse()),
+            facing.turn(&Angle::a180()),
+        ];
+        let dir = tries.iter().cloned().find(|d| game.can_move(*d));
+        if dir.is_some() {
+            self.facing = dir;
+        }
+        dir
+    }
+    fn reset(&mut self) {
+        self.facing = None;
+    }
+}
+
+/// Trémaux's algorithm: mark each passage on the way through it, and
+/// never go down one that has been marked twice.  Finds the way out
+/// of any maze, loops and all.
+#[derive(Debug, Default)]
+pub struct Tremaux {
+    /// Times each passage between two tiles has been walked
+    marks: HashMap<(Loc, Dir), u8>,
+    visited: HashSet<Loc>,
+    /// The tile last left, and the way it was left
+    came: Option<(Loc, Dir)>,
+}
+
+impl Tremaux {
+    pub fn new() -> Tremaux {
+        Tremaux::default()
+    }
+    /// The passage leading from loc in dir, the same from either end
+    fn passage(loc: Loc, dir: Dir) -> (Loc, Dir) {
+        match dir.offset() {
+            (0, -1) | (-1, 0) => (loc.adj(dir), dir.turn(&Angle::a180())),
+            _ => (loc, dir),
+        }
+    }
+    fn marks(&self, loc: Loc, dir: Dir) -> u8 {
+        *self.marks.get(&Tremaux::passage(loc, dir)).unwrap_or(&0)
+    }
+}
+
+impl Strategy for Tremaux {
+    fn choose(&mut self, game: &Game, here: Loc) -> Option<Dir> {
+        let open = open_dirs(game);
+        // The way back down the passage just walked, unless it was
+        // left by teleporter
+        let back = match self.came {
+            Some((from, d)) if from.adj(d) == here => Some(d.turn(&Angle::a180())),
+            _ => None,
+        };
+        let been_here = !self.visited.insert(here);
+        let dir = match back {
+            // Coming down a new passage to somewhere already been,
+            // turn straight back
+            Some(b) if been_here && self.marks(here, b) == 1 && open.len() > 1 => Some(b),
+            _ => {
+                // A passage not walked yet, or else one walked once,
+                // going back the way we came only as a last resort
+                let others = open.iter().cloned().filter(|d| Some(*d) != back);
+                let best = others.min_by_key(|d| self.marks(here, *d)).filter(|d| self.marks(here, *d) < 2);
+                best.or_else(|| back.filter(|b| self.marks(here, *b) < 2))
+            },
+        };
+        if let Some(d) = dir {
+            *self.marks.entry(Tremaux::passage(here, d)).or_insert(0) += 1;
+            self.came = Some((here, d));
+        }
+        dir
+    }
+    fn reset(&mut self) {
+        *self = Tremaux::default();
+    }
+}
+
+/// Knows the whole maze, and walks the shortest route to the goal
+/// found by an A* search, picking up keys on the way
+#[derive(Debug, Default)]
+pub struct AStar {
+    /// The tiles still to walk, starting with the one last reached
+    route: Vec<Loc>,
+}
+
+impl AStar {
+    pub fn new() -> AStar {
+        AStar::default()
+    }
+}
+
+impl Strategy for AStar {
+    fn choose(&mut self, game: &Game, here: Loc) -> Option<Dir> {
+        match self.route.first() {
+            Some(l) if *l == here => (),
+            // Off the route, or without one, so plan a new one
+            _ => self.route = solver::astar(&game.maze, here, &game.inventory)?,
+        }
+        let next = *self.route.get(1)?;
+        self.route.remove(0);
+        // Teleporters land somewhere else, so the next step on the
+        // route may be to a teleporter's partner
+        let teleports = game.maze.teleporters();
+        solver::walk_dirs().iter().cloned().find(|d| {
+            let to = here.adj(*d);
+            to == next || teleports.get(&to) == Some(&next)
+        })
+    }
+    fn reset(&mut self) {
+        self.route.clear();
+    }
+}
+
+/// The bots there are, by name
+pub const BOT_NAMES: [&str; 4] = ["left", "right", "tremaux", "astar"];
+
+/// Make a bot by its name
+pub fn bot(name: &str) -> Option<Box<dyn Controller>> {
+    match name {
+        "left" => Some(Box::new(Bot::new(WallFollower::new(Hand::Left)))),
+        "right" => Some(Box::new(Bot::new(WallFollower::new(Hand::Right)))),
+        "tremaux" => Some(Box::new(Bot::new(Tremaux::new()))),
+        "astar" => Some(Box::new(Bot::new(AStar::new()))),
+        _ => None,
+    }
+}
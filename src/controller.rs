@@ -0,0 +1,41 @@
+use piston_window::ButtonState;
+
+use crate::controls::*;
+use crate::game::*;
+
+/// Something that plays the game, by pressing and letting go of
+/// actions' buttons
+pub trait Controller {
+    /// Actions to take just before the game's next step
+    fn actions(&mut self, game: &Game) -> Vec<(Action, ButtonState)>;
+    /// Take in what a person did with the controls since the last
+    /// step.  Only a person at the controls pays any attention.
+    fn input(&mut self, _actions: &[(Action, ButtonState)]) {}
+    /// Whether there's nothing more to do, so the game should stop
+    /// where it is
+    fn done(&self, _game: &Game) -> bool {
+        false
+    }
+}
+
+/// A person playing with the keyboard or a gamepad
+#[derive(Debug, Default)]
+pub struct Keyboard {
+    /// Actions taken since the last step
+    pending: Vec<(Action, ButtonState)>,
+}
+
+impl Keyboard {
+    pub fn new() -> Keyboard {
+        Keyboard::default()
+    }
+}
+
+impl Controller for Keyboard {
+    fn actions(&mut self, _game: &Game) -> Vec<(Action, ButtonState)> {
+        std::mem::take(&mut self.pending)
+    }
+    fn input(&mut self, actions: &[(Action, ButtonState)]) {
+        self.pending.extend_from_slice(actions);
+    }
+}
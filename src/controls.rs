@@ -0,0 +1,230 @@
+use piston_window::*;
+use serde::Deserialize;
+
+use std::collections::{HashMap,HashSet};
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::io;
+
+use crate::save::invalid;
+
+/// How far a stick has to be pushed before it counts as pressed
+const DEADZONE: f64 = 0.5;
+
+/// Something the player can do by pressing a button
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Deserialize)]
+pub enum Action {
+    MoveNorth,
+    MoveSouth,
+    MoveWest,
+    MoveEast,
+    ToggleLight,
+    ToggleBeam,
+    ToggleModel,
+    ToggleMinimap,
+    ToggleRetro,
+    ToggleFree,
+    CycleCorners,
+    GammaDown,
+    GammaUp,
+    Pause,
+    /// Pick the highlighted choice on a menu
+    Confirm,
+    /// Leave a menu, or pause the game
+    Back,
+}
+
+/// A button, direction or stick push on a gamepad.  In a controls
+/// file these are written as "button 0", "hat up" or "axis 1-",
+/// where the sign says which way the stick is pushed.
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Pad {
+    Button(u8),
+    Hat(HatState),
+    Axis(u8, bool),
+}
+
+impl TryFrom<String> for Pad {
+    type Error = String;
+    fn try_from(s: String) -> Result<Pad, String> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let bad = || format!("Don't know the gamepad input \"{}\".", s);
+        match words.as_slice() {
+            ["button", n] => n.parse().map(Pad::Button).map_err(|_| bad()),
+            ["hat", "up"] => Ok(Pad::Hat(HatState::Up)),
+            ["hat", "down"] => Ok(Pad::Hat(HatState::Down)),
+            ["hat", "left"] => Ok(Pad::Hat(HatState::Left)),
+            ["hat", "right"] => Ok(Pad::Hat(HatState::Right)),
+            ["axis", a] if a.ends_with('+') || a.ends_with('-') => {
+                let (n, sign) = a.split_at(a.len() - 1);
+                n.parse().map(|n| Pad::Axis(n, sign == "+")).map_err(|_| bad())
+            },
+            _ => Err(bad()),
+        }
+    }
+}
+
+/// The straight directions making up a hat position
+fn hat_dirs(state: HatState) -> Vec<HatState> {
+    use HatState::*;
+    match state {
+        Centered => vec![],
+        RightUp => vec![Right, Up],
+        RightDown => vec![Right, Down],
+        LeftUp => vec![Left, Up],
+        LeftDown => vec![Left, Down],
+        s => vec![s],
+    }
+}
+
+/// Bindings as written in a controls file: for each action, the
+/// keys and gamepad inputs that trigger it.  An action left out of
+/// the file keeps its default bindings.
+#[derive(Debug, Deserialize)]
+struct Profile {
+    #[serde(default)]
+    keys: HashMap<String, Vec<Key>>,
+    #[serde(default)]
+    gamepad: HashMap<String, Vec<Pad>>,
+}
+
+/// Look up the actions named in a section of a controls file.  The
+/// toml crate can't read enums from table keys by itself, so each
+/// name is read as a value instead.
+fn named<I>(section: HashMap<String, Vec<I>>) -> Result<HashMap<Action, Vec<I>>, toml::de::Error> {
+    section.into_iter()
+        .map(|(name, inputs)| Ok((Action::deserialize(toml::Value::String(name))?, inputs)))
+        .collect()
+}
+
+fn default_keys() -> HashMap<Action, Vec<Key>> {
+    use Action::*;
+    vec![
+        (MoveNorth, vec![Key::W]),
+        (MoveSouth, vec![Key::S]),
+        (MoveWest, vec![Key::A]),
+        (MoveEast, vec![Key::D]),
+        (ToggleLight, vec![Key::F]),
+        (ToggleBeam, vec![Key::B]),
+        (ToggleModel, vec![Key::L]),
+        (ToggleMinimap, vec![Key::M]),
+        (ToggleRetro, vec![Key::R]),
+        (ToggleFree, vec![Key::T]),
+        (CycleCorners, vec![Key::C]),
+        (GammaDown, vec![Key::LeftBracket]),
+        (GammaUp, vec![Key::RightBracket]),
+        (Pause, vec![Key::P]),
+        (Confirm, vec![Key::Return, Key::Space]),
+        (Back, vec![Key::Escape]),
+    ].into_iter().collect()
+}
+
+fn default_gamepad() -> HashMap<Action, Vec<Pad>> {
+    use Action::*;
+    vec![
+        (MoveNorth, vec![Pad::Hat(HatState::Up), Pad::Axis(1, false)]),
+        (MoveSouth, vec![Pad::Hat(HatState::Down), Pad::Axis(1, true)]),
+        (MoveWest, vec![Pad::Hat(HatState::Left), Pad::Axis(0, false)]),
+        (MoveEast, vec![Pad::Hat(HatState::Right), Pad::Axis(0, true)]),
+        (Confirm, vec![Pad::Button(0)]),
+        (Back, vec![Pad::Button(1)]),
+        (ToggleLight, vec![Pad::Button(2)]),
+        (ToggleBeam, vec![Pad::Button(3)]),
+        (ToggleMinimap, vec![Pad::Button(6)]),
+        (Pause, vec![Pad::Button(7)]),
+    ].into_iter().collect()
+}
+
+/// Turn bindings around, to look up the action for an input.  An
+/// input can only trigger one action.
+fn flip<I: Eq + Hash + Debug>(bindings: HashMap<Action, Vec<I>>) -> Result<HashMap<I, Action>, String> {
+    let mut flipped = HashMap::new();
+    for (a, inputs) in bindings {
+        for i in inputs {
+            if let Some(other) = flipped.get(&i) {
+                return Err(format!("{:?} is bound to both {:?} and {:?}.", i, other, a));
+            }
+            flipped.insert(i, a);
+        }
+    }
+    Ok(flipped)
+}
+
+/// Turns keyboard and gamepad events into actions
+#[derive(Debug)]
+pub struct Controls {
+    keys: HashMap<Key, Action>,
+    pads: HashMap<Pad, Action>,
+    /// Hat directions and stick pushes currently held, which only
+    /// send their new position rather than a press and a release
+    held: HashSet<Pad>,
+}
+
+impl Default for Controls {
+    fn default() -> Controls {
+        Controls::new(default_keys(), default_gamepad()).expect("default bindings overlap")
+    }
+}
+
+impl Controls {
+    fn new(keys: HashMap<Action, Vec<Key>>, gamepad: HashMap<Action, Vec<Pad>>) -> Result<Controls, String> {
+        Ok(Controls{
+            keys: flip(keys)?,
+            pads: flip(gamepad)?,
+            held: HashSet::new(),
+        })
+    }
+    /// Read bindings from a controls file, each one taking the place
+    /// of the default for its action
+    pub fn load(fname: &str) -> io::Result<Controls> {
+        let contents = std::fs::read_to_string(fname)?;
+        let profile: Profile = toml::from_str(&contents).map_err(invalid)?;
+        let mut keys = default_keys();
+        keys.extend(named(profile.keys).map_err(invalid)?);
+        let mut gamepad = default_gamepad();
+        gamepad.extend(named(profile.gamepad).map_err(invalid)?);
+        Controls::new(keys, gamepad).map_err(invalid)
+    }
+    /// The actions started or stopped by an event
+    pub fn actions<E: GenericEvent>(&mut self, e: &E) -> Vec<(Action, ButtonState)> {
+        let mut changes = Vec::new();
+        if let Some(args) = e.button_args() {
+            let action = match args.button {
+                Button::Keyboard(k) => self.keys.get(&k),
+                Button::Controller(b) => self.pads.get(&Pad::Button(b.button)),
+                Button::Hat(h) => {
+                    // A hat says where it points now, which may let go
+                    // of the directions it pointed before
+                    let now = match args.state {
+                        ButtonState::Press => hat_dirs(h.state),
+                        ButtonState::Release => vec![],
+                    };
+                    for d in [HatState::Up, HatState::Down, HatState::Left, HatState::Right].iter() {
+                        self.set(Pad::Hat(*d), now.contains(d), &mut changes);
+                    }
+                    None
+                },
+                _ => None,
+            };
+            if let Some(a) = action {
+                changes.push((*a, args.state));
+            }
+        }
+        if let Some(args) = e.controller_axis_args() {
+            self.set(Pad::Axis(args.axis, true), args.position > DEADZONE, &mut changes);
+            self.set(Pad::Axis(args.axis, false), args.position < -DEADZONE, &mut changes);
+        }
+        changes
+    }
+    /// Note whether a hat direction or stick push is held, adding
+    /// its action to changes if that's different from before
+    fn set(&mut self, pad: Pad, on: bool, changes: &mut Vec<(Action, ButtonState)>) {
+        let changed = if on { self.held.insert(pad) } else { self.held.remove(&pad) };
+        if let (true, Some(a)) = (changed, self.pads.get(&pad)) {
+            let state = if on { ButtonState::Press } else { ButtonState::Release };
+            changes.push((*a, state));
+        }
+    }
+}
@@ -0,0 +1,726 @@
+use piston_window::ButtonState;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::geometry::*;
+use crate::mazes::*;
+use crate::light::*;
+use crate::battery::*;
+use crate::input::*;
+use crate::controls::*;
+use crate::levels::*;
+use crate::score::*;
+use crate::save::*;
+use crate::solver;
+use LocMode::{Complete,InProgress,Free};
+
+/// Draw distance
+pub const DRAW_DIST: isize = 10;
+
+/// Seconds of play between each place noted down in the trace
+pub const TRACE_TICK: f64 = 0.1;
+
+/// Seconds of play in each step of the game.  Stepping by a fixed
+/// amount makes a game play out the same way every time it is given
+/// the same seed and actions, whatever the frame rate.
+pub const STEP: f64 = 1.0 / 60.0;
+
+/// Distance cam falls behind before following
+const CAM_DIST: f64 = 1.0;
+
+/// Half the width of the player, for bumping into walls while moving
+/// freely
+const FREE_RADIUS: f64 = 0.3;
+
+/// Starting gamma for smooth shading, and the limits it can be
+/// adjusted between
+const GAMMA: f64 = 1.5;
+const GAMMA_RANGE: (f64, f64) = (0.5, 3.0);
+
+/// Where the player is, and how they are getting about
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "mode")]
+pub enum LocMode {
+    /// Standing on a tile
+    Complete(Loc),
+    /// Walking from one tile to the next
+    InProgress(TileRoute),
+    /// Moving freely, without being locked to tiles
+    Free(FineLoc),
+}
+
+impl From<RouteResult> for LocMode {
+    fn from(r: RouteResult) -> LocMode {
+        match r {
+            RouteResult::Complete(l) => Complete(l),
+            RouteResult::InProgress(r) => InProgress(r),
+        }
+    }
+}
+
+/// Rules for stepping diagonally past the corners of walls, from
+/// strictest to loosest
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Corners {
+    /// Only when both tiles beside the step are open
+    Strict,
+    /// As long as one of the tiles beside the step is open
+    Squeeze,
+    /// Whenever the tile being stepped to is open, even between two
+    /// walls
+    Free,
+}
+
+impl Corners {
+    /// Switch to the next rule, from strictest to loosest
+    fn next(self) -> Corners {
+        match self {
+            Corners::Strict => Corners::Squeeze,
+            Corners::Squeeze => Corners::Free,
+            Corners::Free => Corners::Strict,
+        }
+    }
+    /// Can a step be taken in dir, given which directions lead to
+    /// tiles that can be walked onto?  Diagonal steps also have to
+    /// get past the tiles to either side.
+    pub fn allow(self, dir: Dir, open: impl Fn(Dir) -> bool) -> bool {
+        match dir.split() {
+            None => open(dir),
+            Some((a, b)) => open(dir) && match self {
+                Corners::Strict => open(a) && open(b),
+                Corners::Squeeze => open(a) || open(b),
+                Corners::Free => true,
+            },
+        }
+    }
+}
+
+/// Which screen the game is on
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum State {
+    Title,
+    Playing,
+    Paused,
+    /// The goal was reached
+    Won,
+    /// The flashlight's battery ran out
+    Lost,
+    /// The player asked to leave
+    Quit,
+}
+
+/// Something to pick from a menu
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Choice {
+    Start,
+    /// Pick up the saved game where it was left
+    Continue,
+    Resume,
+    /// Go through the same maze again from the start
+    Retry,
+    NextLevel,
+    ToTitle,
+    /// Leave partway through a level, to carry on next time
+    SaveQuit,
+    Quit,
+}
+
+impl Choice {
+    pub fn label(self) -> &'static str {
+        match self {
+            Choice::Start => "Start",
+            Choice::Continue => "Continue",
+            Choice::Resume => "Resume",
+            Choice::Retry => "Try this maze again",
+            Choice::NextLevel => "Next level",
+            Choice::ToTitle => "Back to title",
+            Choice::SaveQuit => "Save and quit",
+            Choice::Quit => "Quit",
+        }
+    }
+}
+
+pub struct Game {
+    /// The campaign being played, and how far into it we are
+    pub pack: Pack,
+    pub level: usize,
+    pub maze: Maze,
+    /// The maze as it was at the start, for trying it again
+    fresh: Maze,
+    /// The seed the maze was generated from, if it was
+    pub maze_seed: Option<u32>,
+    pub state: State,
+    /// Which choice is picked out on the current menu
+    pub selected: usize,
+    pub loc: LocMode,
+    pub intent: Intent,
+    pub corners: Corners,
+    /// The loosest corner rules used so far this run, which par is
+    /// worked out under so that switching rules partway can't make
+    /// it easier
+    pub loosest: Corners,
+    pub speed: f64, // in tiles/sec
+    pub dir: Dir,
+    pub camera: FineLoc,
+    /// Whether the player was just teleported, so the camera should
+    /// jump to them rather than follow
+    teleported: bool,
+    pub battery: Battery,
+    pub light_on: bool,
+    pub beam: Beam,
+    pub inventory: HashSet<KeyColor>,
+    pub light_model: Model,
+    /// Light around the camera, filled in each frame
+    pub lighting: Lighting,
+    /// Every location that has ever been lit up enough to see
+    pub explored: HashSet<Loc>,
+    pub show_minimap: bool,
+    /// Whether the minimap gives away where the goal is, even before
+    /// it has been found.  Set by the level, for easier games.
+    pub minimap_goal: bool,
+    /// Use the classic three steps of light instead of smooth shading
+    pub retro_shading: bool,
+    /// Brightness of smoothly shaded tiles follows the light raised
+    /// to 1/gamma, so a gamma above 1 brings out dimly lit tiles
+    pub gamma: f64,
+    /// Seconds spent playing, not counting time paused
+    pub time: f64,
+    /// Tiles walked onto so far
+    pub walked: usize,
+    /// Where the player has been, every TRACE_TICK seconds of play
+    pub trace: Vec<(f64, f64)>,
+    /// What a good run through this level looks like
+    pub par: Option<Par>,
+    /// A level left partway through last time, offered on the title
+    /// screen until it is picked up
+    pub saved: Option<Save>,
+    /// Whether the player quit partway through a level
+    left_level: bool,
+    /// Steps taken since the game began, for lining actions up with
+    pub steps: u64,
+    /// Decides everything left to chance, from the game's seed
+    rng: StdRng,
+}
+
+impl Game {
+    /// Make a new game for a campaign, starting at the title screen
+    /// with the first level ready to go.  The seed decides anything
+    /// left to chance, such as mazes without seeds of their own and
+    /// how the flashlight flickers.
+    pub fn new(pack: Pack, seed: u64) -> Game {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let seed = rng.gen();
+        let maze = pack.levels[0].maze(seed);
+        let maze_seed = pack.levels[0].maze_seed(seed);
+        Game::on_level(pack, 0, maze, maze_seed, rng)
+    }
+    fn on_level(pack: Pack, level: usize, maze: Maze, maze_seed: Option<u32>, mut rng: StdRng) -> Game {
+        let start_loc = maze.start;
+        let battery = pack.levels[level].battery_config();
+        let speed = 3.0;
+        let par = Par::new(&maze, speed, pack.levels[level].par, Corners::Strict);
+        let minimap_goal = pack.levels[level].minimap_goal;
+        Game{
+            pack,
+            level,
+            lighting: Lighting::new(&maze, DRAW_DIST + 1),
+            fresh: maze.clone(),
+            maze,
+            maze_seed,
+            state: State::Title,
+            selected: 0,
+            loc: Complete(start_loc),
+            dir: Dir::south(),
+            speed,
+            intent: Intent::new(),
+            corners: Corners::Strict,
+            loosest: Corners::Strict,
+            camera: FineLoc::from_loc(start_loc),
+            teleported: false,
+            battery: Battery::new(battery, rng.gen()),
+            light_on: true,
+            beam: Beam::Narrow,
+            inventory: HashSet::new(),
+            light_model: Model::Rays,
+            explored: HashSet::new(),
+            show_minimap: true,
+            minimap_goal,
+            retro_shading: false,
+            gamma: GAMMA,
+            time: 0.0,
+            walked: 0,
+            trace: Vec::new(),
+            par,
+            saved: None,
+            left_level: false,
+            steps: 0,
+            rng,
+        }
+    }
+    /// Start playing a level of the campaign in the given maze, from
+    /// scratch but keeping the player's settings
+    fn restart(&mut self, level: usize, maze: Maze, maze_seed: Option<u32>) {
+        let fresh = Game::on_level(self.pack.clone(), level, maze, maze_seed, self.rng.clone());
+        *self = Game{
+            state: State::Playing,
+            corners: self.corners,
+            loosest: self.corners,
+            beam: self.beam,
+            light_model: self.light_model,
+            show_minimap: self.show_minimap,
+            retro_shading: self.retro_shading,
+            gamma: self.gamma,
+            saved: self.saved.take(),
+            steps: self.steps,
+            ..fresh
+        };
+        self.update_par();
+    }
+    /// Start a level afresh in a new maze
+    fn new_maze(&mut self, level: usize) {
+        let seed = self.rng.gen();
+        let maze = self.pack.levels[level].maze(seed);
+        let maze_seed = self.pack.levels[level].maze_seed(seed);
+        self.restart(level, maze, maze_seed);
+    }
+    /// What the level's ghost is filed under, if it can have one
+    pub fn ghost_key(&self) -> Option<String> {
+        self.pack.ghost_key(self.level, self.maze_seed)
+    }
+    /// Work out par again, under the loosest corner rules used
+    fn update_par(&mut self) {
+        self.par = Par::new(&self.fresh, self.speed, self.pack.levels[self.level].par, self.loosest);
+    }
+    /// Carry on with a saved level, paused so the player can get
+    /// their bearings.  A save whose mazes can't be read is dropped.
+    fn resume(&mut self, save: Save) {
+        let (maze, fresh) = match (save.maze.maze(), save.fresh.maze()) {
+            (Ok(m), Ok(f)) => (m, f),
+            _ => return,
+        };
+        self.restart(save.level, fresh, save.maze_seed);
+        self.lighting = Lighting::new(&maze, DRAW_DIST + 1);
+        self.maze = maze;
+        self.loc = save.loc;
+        self.dir = save.dir;
+        self.camera = save.camera;
+        self.battery.set_charge(save.charge);
+        self.light_on = save.light_on;
+        self.beam = save.beam;
+        self.inventory = save.inventory.into_iter().collect();
+        self.explored = save.explored.into_iter().collect();
+        self.corners = save.corners;
+        self.loosest = save.loosest.unwrap_or(save.corners).max(save.corners);
+        self.update_par();
+        self.light_model = save.light_model;
+        self.show_minimap = save.show_minimap;
+        self.retro_shading = save.retro_shading;
+        self.gamma = save.gamma;
+        self.time = save.time;
+        self.walked = save.walked;
+        self.trace = save.trace;
+        self.enter(State::Paused);
+    }
+    /// The maze as it was at the start of the level
+    pub fn fresh(&self) -> &Maze {
+        &self.fresh
+    }
+    /// Is a level being played, that would be lost on quitting?
+    pub fn in_progress(&self) -> bool {
+        match self.state {
+            State::Playing | State::Paused => true,
+            State::Quit => self.left_level,
+            _ => false,
+        }
+    }
+    /// Move to another screen
+    fn enter(&mut self, state: State) {
+        self.state = state;
+        self.selected = 0;
+    }
+    /// Get tile at loc
+    fn tile_at(&self, loc: Loc) -> Option<Tile> {
+        self.maze.map.get(&loc).copied()
+    }
+    /// Get tile adjecent to current loc, in given direction
+    fn adj(&self, dir: Dir) -> Option<Tile> {
+        match self.loc {
+            InProgress(r) => self.tile_at(r.start.adj(dir)),
+            _ => self.tile_at(self.base_loc().adj(dir)),
+        }
+    }
+    /// Can the tile at loc be walked onto, with the keys currently in
+    /// the inventory?
+    fn open(&self, loc: Loc) -> bool {
+        match self.tile_at(loc) {
+            Some(t) => t.passable(&self.inventory),
+            None => false,
+        }
+    }
+    /// Can the tile adjacent to current loc be walked onto, with the
+    /// keys currently in the inventory?  Diagonal steps also have to
+    /// get past the tiles to either side, as the corner rules say.
+    pub fn can_move(&self, dir: Dir) -> bool {
+        let open = |d| match self.adj(d) {
+            Some(t) => t.passable(&self.inventory),
+            None => false,
+        };
+        self.corners.allow(dir, open)
+    }
+    /// Handle finishing a move onto loc: pick up the key there, if
+    /// there is one, or jump to the partner of a teleporter.
+    fn arrive(&mut self, loc: Loc) {
+        match self.tile_at(loc) {
+            Some(Tile::Key(c)) => {
+                self.inventory.insert(c);
+                self.maze.map.insert(loc, Tile::Floor);
+            },
+            Some(Tile::Teleporter(_)) => {
+                if let Some(dest) = self.maze.teleporters().get(&loc) {
+                    self.loc = match self.loc {
+                        Free(_) => Free(FineLoc::from_loc(*dest)),
+                        _ => Complete(*dest),
+                    };
+                    self.teleported = true;
+                }
+            },
+            _ => (),
+        }
+    }
+    /// Move the game on by one step, lighting up the maze as it is
+    /// at the end of it
+    pub fn step(&mut self) {
+        self.update(STEP);
+        self.settle_cam();
+        self.light_up();
+        self.steps += 1;
+    }
+    /// Run down the battery, and update position if in motion,
+    /// otherwise set into motion if there is intent.  Nothing happens
+    /// unless the game is being played.
+    fn update(&mut self, dt: f64) {
+        if self.state != State::Playing {
+            return;
+        }
+        self.time += dt;
+        let was = self.loc;
+        match self.loc {
+            InProgress(route) => {
+                self.loc = route.advance(dt * self.speed / route.length()).into();
+                if let Complete(loc) = self.loc {
+                    self.walked += 1;
+                    self.arrive(loc);
+                }
+            },
+            Complete(loc) => {
+                let choices = self.intent.choices();
+                match choices.iter().find(|d| self.can_move(**d)) {
+                    Some(d) => {
+                        // Any way on other than straight ahead or
+                        // back makes this a junction
+                        let sides = solver::walk_dirs().iter()
+                            .filter(|s| **s != self.dir && **s != self.dir.turn(&Angle::a180()))
+                            .filter(|s| self.can_move(**s))
+                            .count();
+                        if sides > 0 {
+                            self.intent.passed_junction(*d);
+                        }
+                        self.dir = *d;
+                        self.intent.moved(*d);
+                        self.loc = InProgress(TileRoute::new(loc,*d));
+                    },
+                    None => {
+                        // Nowhere to go, so face the way we'd like
+                        // to and drop any turn that was saved up
+                        if let Some(d) = self.intent.intended() {
+                            self.dir = d;
+                        }
+                        self.intent.clear_buffer();
+                    },
+                }
+            },
+            Free(pos) => if let Some(d) = self.intent.intended() {
+                self.dir = d;
+                let (dx, dy) = d.offset();
+                let step = dt * self.speed / ((dx * dx + dy * dy) as f64).sqrt();
+                let (x, y) = pos.as_coords();
+                let x = self.slide(x, y, dx as f64 * step, true);
+                let y = self.slide(x, y, dy as f64 * step, false);
+                let before = self.base_loc();
+                self.loc = Free(FineLoc::from_coords((x, y)));
+                let after = self.base_loc();
+                if after != before {
+                    self.walked += 1;
+                    self.arrive(after);
+                }
+            },
+        }
+
+        // Moving freely, pushing against a wall doesn't count as
+        // walking
+        let moving = match (was, self.loc) {
+            (InProgress(_), _) => true,
+            (Free(a), Free(b)) => a != b,
+            _ => false,
+        };
+        // With the flashlight off, the battery gets a rest, though
+        // not a complete one
+        let load = if self.light_on { self.beam.load() } else { OFF_LOAD };
+        self.battery.update(dt, moving, load);
+
+        while self.trace.len() as f64 * TRACE_TICK <= self.time {
+            self.trace.push(self.fine_loc().as_coords());
+        }
+
+        if self.base_loc() == self.maze.goal {
+            // End the trace right on the goal
+            self.trace.push(self.fine_loc().as_coords());
+            self.enter(State::Won);
+        } else if self.battery.is_dead() {
+            self.enter(State::Lost);
+        }
+    }
+    /// Does a player standing freely at (x,y) overlap anything that
+    /// can't be walked through?
+    fn blocked(&self, x: f64, y: f64) -> bool {
+        let (lo, hi) = (
+            FineLoc::from_coords((x - FREE_RADIUS, y - FREE_RADIUS)).nearest(),
+            FineLoc::from_coords((x + FREE_RADIUS, y + FREE_RADIUS)).nearest(),
+        );
+        (lo.x..=hi.x).any(|tx| (lo.y..=hi.y).any(|ty| !self.open(Loc{x: tx, y: ty})))
+    }
+    /// Move freely along one axis, from (x,y) by delta, stopping
+    /// flush against anything in the way.  Gives the new coordinate
+    /// along that axis.
+    fn slide(&self, x: f64, y: f64, delta: f64, horizontal: bool) -> f64 {
+        let from = if horizontal { x } else { y };
+        let to = from + delta;
+        let at = |v| if horizontal { (v, y) } else { (x, v) };
+        let (tx, ty) = at(to);
+        if !self.blocked(tx, ty) {
+            return to;
+        }
+        // Tiles span half a tile either side of their centers
+        let edge = if delta > 0.0 {
+            (to + FREE_RADIUS + 0.5).floor() - 0.5 - FREE_RADIUS - 1e-6
+        } else {
+            (to - FREE_RADIUS + 0.5).floor() + 0.5 + FREE_RADIUS + 1e-6
+        };
+        let (ex, ey) = at(edge);
+        if (edge - from) * delta > 0.0 && !self.blocked(ex, ey) {
+            edge
+        } else {
+            from
+        }
+    }
+    /// Carry out an action, on its button being pressed or let go.
+    /// What an action does depends on the screen.
+    pub fn act(&mut self, action: Action, state: ButtonState) {
+        use Action::*;
+        let dir = match action {
+            MoveNorth => Some(Dir::north()),
+            MoveSouth => Some(Dir::south()),
+            MoveWest => Some(Dir::west()),
+            MoveEast => Some(Dir::east()),
+            _ => None,
+        };
+        // Keep track of held directions even on menus, so that
+        // nothing is left held down on getting back to the game
+        match (dir, state) {
+            (Some(d), ButtonState::Press) => self.intent.press(d, matches!(self.loc, InProgress(_))),
+            (Some(d), ButtonState::Release) => self.intent.release(d),
+            _ => (),
+        }
+        if state != ButtonState::Press {
+            return;
+        }
+        match self.state {
+            State::Playing => match action {
+                ToggleLight => self.light_on = !self.light_on,
+                ToggleBeam => self.beam = self.beam.toggle(),
+                ToggleModel => self.light_model = self.light_model.toggle(),
+                ToggleMinimap => self.show_minimap = !self.show_minimap,
+                ToggleRetro => self.retro_shading = !self.retro_shading,
+                ToggleFree => self.toggle_free(),
+                CycleCorners => {
+                    self.corners = self.corners.next();
+                    self.loosest = self.loosest.max(self.corners);
+                    self.update_par();
+                },
+                GammaDown => self.adjust_gamma(-0.1),
+                GammaUp => self.adjust_gamma(0.1),
+                Pause | Back => self.enter(State::Paused),
+                _ => (),
+            },
+            _ => self.navigate(action),
+        }
+    }
+    /// Is this the last level of the campaign?
+    pub fn last_level(&self) -> bool {
+        self.level + 1 >= self.pack.levels.len()
+    }
+    /// The choices on the current screen, if it has a menu
+    pub fn menu(&self) -> Vec<Choice> {
+        match self.state {
+            State::Title if self.saved.is_some() => vec![Choice::Continue, Choice::Start, Choice::Quit],
+            State::Title => vec![Choice::Start, Choice::Quit],
+            State::Paused => vec![Choice::Resume, Choice::Retry, Choice::ToTitle, Choice::SaveQuit],
+            State::Won if !self.last_level() => vec![Choice::NextLevel, Choice::Retry, Choice::ToTitle],
+            State::Won => vec![Choice::ToTitle, Choice::Retry],
+            State::Lost => vec![Choice::Retry, Choice::ToTitle],
+            State::Playing | State::Quit => vec![],
+        }
+    }
+    /// Handle an action on a menu screen
+    fn navigate(&mut self, action: Action) {
+        let count = self.menu().len();
+        match action {
+            Action::MoveNorth if count > 0 => self.selected = (self.selected + count - 1) % count,
+            Action::MoveSouth if count > 0 => self.selected = (self.selected + 1) % count,
+            Action::Confirm => {
+                if let Some(choice) = self.menu().get(self.selected) {
+                    self.choose(*choice);
+                }
+            },
+            Action::Back => match self.state {
+                State::Title => self.enter(State::Quit),
+                State::Paused => self.enter(State::Playing),
+                _ => self.choose(Choice::ToTitle),
+            },
+            Action::Pause if self.state == State::Paused => self.enter(State::Playing),
+            _ => (),
+        }
+    }
+    fn choose(&mut self, choice: Choice) {
+        match choice {
+            Choice::Start | Choice::Resume => self.enter(State::Playing),
+            Choice::Continue => {
+                if let Some(save) = self.saved.take() {
+                    self.resume(save);
+                }
+            },
+            Choice::Retry => self.restart(self.level, self.fresh.clone(), self.maze_seed),
+            Choice::NextLevel => self.new_maze(self.level + 1),
+            Choice::ToTitle => {
+                self.new_maze(0);
+                self.enter(State::Title);
+            },
+            Choice::SaveQuit => {
+                self.left_level = true;
+                self.enter(State::Quit);
+            },
+            Choice::Quit => self.enter(State::Quit),
+        }
+    }
+    /// Switch between walking tile by tile and moving freely.  Going
+    /// back to tiles snaps to the nearest one.  Nothing changes
+    /// partway through a step between tiles.
+    fn toggle_free(&mut self) {
+        self.loc = match self.loc {
+            Complete(l) => Free(FineLoc::from_loc(l)),
+            Free(_) => Complete(self.base_loc()),
+            InProgress(r) => InProgress(r),
+        };
+        // Taps made while moving freely were never meant as turns
+        self.intent.clear_buffer();
+    }
+    /// The flashlight's light, if it is on, shining from loc
+    fn flashlight(&self, loc: Loc) -> Option<Source> {
+        if self.light_on {
+            let optics = &self.pack.levels[self.level].flashlight;
+            Some(self.beam.source(optics, loc, self.dir, self.battery.power()))
+        } else {
+            None
+        }
+    }
+    fn adjust_gamma(&mut self, by: f64) {
+        self.gamma = (self.gamma + by).max(GAMMA_RANGE.0).min(GAMMA_RANGE.1);
+    }
+    /// Places the flashlight shines from this frame, and how much of
+    /// its light comes from each
+    fn flashlight_spots(&self) -> Vec<(Loc, f64)> {
+        match self.loc {
+            // If we are moving, perform a second illumination from
+            // the point of view of our destination and blend the two
+            // together.
+            InProgress(r) => vec![
+                (self.base_loc(), 1.0 - r.get_progress()),
+                (r.dest(), r.get_progress()),
+            ],
+            Complete(l) => vec![(l, 1.0)],
+            // Moving freely, light from the tiles around our position,
+            // each weighted by how close we are to it.  Whatever share
+            // falls on a wall is lost, so the light dims a little as
+            // we brush past a corner.
+            Free(f) => {
+                let (x, y) = f.as_coords();
+                let (fx, fy) = (x - x.floor(), y - y.floor());
+                let corner = Loc{x: x.floor() as Int, y: y.floor() as Int};
+                vec![
+                    (corner, (1.0 - fx) * (1.0 - fy)),
+                    (corner + Loc{x: 1, y: 0}, fx * (1.0 - fy)),
+                    (corner + Loc{x: 0, y: 1}, (1.0 - fx) * fy),
+                    (corner + Loc{x: 1, y: 1}, fx * fy),
+                ].into_iter()
+                .filter(|(l, w)| *w > 0.0 && self.maze.map.get(l).is_some_and(|t| t.transparent()))
+                .collect()
+            },
+        }
+    }
+    /// Fill in the light around the camera for this frame, and
+    /// remember everything that can be seen in it
+    pub fn light_up(&mut self) {
+        self.lighting.reset(self.camera.base);
+        for (loc, factor) in self.flashlight_spots() {
+            if let Some(source) = self.flashlight(loc) {
+                self.lighting.illuminate(&self.maze, self.light_model, &source, factor);
+            }
+        }
+        // Fixed lamps shine whether or not the flashlight reaches them
+        self.lighting.illuminate_lamps(&self.maze, self.light_model);
+        self.explored.extend(self.lighting.visible());
+    }
+    /// How the run through this level has gone so far
+    pub fn run(&self) -> Run {
+        Run{
+            time: self.time,
+            tiles: self.walked,
+            battery: self.battery.level(),
+            explored: self.explored_fraction(),
+        }
+    }
+    /// Fraction of the maze's floor that has been explored so far
+    pub fn explored_fraction(&self) -> f64 {
+        let seen = self.maze.map.keys().filter(|l| self.explored.contains(l)).count();
+        seen as f64 / self.maze.map.len().max(1) as f64
+    }
+    pub fn base_loc(&self) -> Loc {
+        match self.loc {
+            Complete(l) => l,
+            InProgress(r) => r.as_fineloc().base,
+            Free(f) => f.nearest(),
+        }
+    }
+    pub fn fine_loc(&self) -> FineLoc {
+        match self.loc {
+            Complete(l) => FineLoc::from_loc(l),
+            InProgress(r) => r.as_fineloc(),
+            Free(f) => f,
+        }
+    }
+    pub fn settle_cam(&mut self) {
+        if self.teleported {
+            // Jump straight to the new spot, however near it is
+            self.teleported = false;
+            self.camera = self.fine_loc();
+        } else {
+            let d = (self.fine_loc() - self.camera).as_coords();
+            // Follow along each axis separately, so that diagonal
+            // moves are followed smoothly too
+            let lag = |d: f64| d.clamp(-CAM_DIST, CAM_DIST);
+            self.camera = self.fine_loc() - FineLoc::from_coords((lag(d.0), lag(d.1)));
+        }
+    }
+}
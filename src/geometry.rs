@@ -1,9 +1,14 @@
-type Int = isize;
+use serde::{Deserialize, Serialize};
+
+use std::ops::{Add, Sub};
+
+pub type Int = isize;
 
 pub const DIR_RESOLUTION: Int = 8;
 
 /// Direction in maze space
-#[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Hash, Serialize, Deserialize)]
+#[serde(from = "Int", into = "Int")]
 pub struct Dir {
     i: Int,
 }
@@ -18,6 +23,20 @@ fn dir(i: Int) -> Dir {
     Dir{i: i3}
 }
 
+/// Directions are saved as their number of eighth turns clockwise
+/// from north
+impl From<Int> for Dir {
+    fn from(i: Int) -> Dir {
+        dir(i)
+    }
+}
+
+impl From<Dir> for Int {
+    fn from(d: Dir) -> Int {
+        d.i
+    }
+}
+
 impl Dir {
     pub fn north() -> Dir { dir(0) }
     pub fn ne() -> Dir    { dir(1) }
@@ -27,7 +46,8 @@ impl Dir {
     pub fn sw() -> Dir    { dir(5) }
     pub fn west() -> Dir  { dir(6) }
     pub fn nw() -> Dir    { dir(7) }
-    fn offset(&self) -> (Int, Int) {
+    /// Get the step in (x,y) taken by moving one tile this way
+    pub fn offset(&self) -> (Int, Int) {
         match self.i {
             0 => (0,-1),
             1 => (1,-1),
@@ -44,15 +64,46 @@ impl Dir {
             ),
         }
     }
+    /// Get the direction whose step is the given (x,y) offset, or
+    /// None for no step at all
+    pub fn from_offset(offset: (Int, Int)) -> Option<Dir> {
+        (0..DIR_RESOLUTION).map(dir).find(|d| d.offset() == offset)
+    }
     pub fn turn(&self, a: &Angle) -> Dir {
         dir(self.i + a.i)
     }
+    pub fn is_diagonal(&self) -> bool {
+        self.i % 2 == 1
+    }
+    /// Get the diagonal halfway between two straight directions at
+    /// right angles to each other
+    pub fn between(a: Dir, b: Dir) -> Option<Dir> {
+        if a.is_diagonal() || b.is_diagonal() {
+            None
+        } else if a.turn(&Angle::a90()) == b {
+            Some(a.turn(&Angle::a45()))
+        } else if b.turn(&Angle::a90()) == a {
+            Some(b.turn(&Angle::a45()))
+        } else {
+            None
+        }
+    }
+    /// Split a diagonal into the two straight directions it lies
+    /// between
+    pub fn split(&self) -> Option<(Dir, Dir)> {
+        if self.is_diagonal() {
+            Some((self.turn(&Angle::a45().reverse()), self.turn(&Angle::a45())))
+        } else {
+            None
+        }
+    }
     pub fn as_int(&self) -> Int {
         self.i
     }
 }
 
 /// Relative direction
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub struct Angle {
     i: Int,
 }
@@ -60,10 +111,16 @@ pub struct Angle {
 impl Angle {
     pub fn a45() -> Angle { Angle { i: DIR_RESOLUTION / 8 } }
     pub fn a90() -> Angle { Angle { i: DIR_RESOLUTION / 4 } }
-    pub fn a180() -> Angle { Angle { i: DIR_RESOLUTION / 1 } }
+    pub fn a180() -> Angle { Angle { i: DIR_RESOLUTION / 2 } }
     pub fn a360() -> Angle { Angle { i: DIR_RESOLUTION } }
     pub fn reverse(&self) -> Angle {
-        Angle { i: &self.i * -1 }
+        Angle { i: -self.i }
+    }
+    pub fn times(&self, n: Int) -> Angle {
+        Angle { i: self.i * n }
+    }
+    pub fn as_int(&self) -> Int {
+        self.i
     }
     pub fn as_dir(&self) -> Dir {
         Dir::north().turn(self)
@@ -71,7 +128,7 @@ impl Angle {
 }
 
 /// An address in maze space
-#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Loc {
     pub x: Int,
     pub y: Int,
@@ -88,16 +145,23 @@ impl Loc {
     pub fn as_coords(self) -> (f64,f64) {
         (self.x as f64, self.y as f64)
     }
+}
 
-    pub fn sub(self, l: Loc) -> Loc {
-        Loc{x: self.x - l.x, y: self.y - l.y}
-    }
-    pub fn add(self, l: Loc) -> Loc {
+impl Add for Loc {
+    type Output = Loc;
+    fn add(self, l: Loc) -> Loc {
         Loc{x: self.x + l.x, y: self.y + l.y}
     }
 }
 
-#[derive(PartialEq, Copy, Clone, Debug)]
+impl Sub for Loc {
+    type Output = Loc;
+    fn sub(self, l: Loc) -> Loc {
+        Loc{x: self.x - l.x, y: self.y - l.y}
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct FineLoc {
     pub base: Loc,
     offsets: (f64,f64),
@@ -128,6 +192,12 @@ impl FineLoc {
         self.offsets
     }
 
+    /// Get the basic Loc whose center is closest
+    pub fn nearest(self) -> Loc {
+        let (x, y) = self.as_coords();
+        Loc{x: x.round() as Int, y: y.round() as Int}
+    }
+
     pub fn step(self, dir: Dir) -> FineLoc {
         FineLoc{
             base: self.base.adj(dir),
@@ -135,18 +205,6 @@ impl FineLoc {
         }
     }
 
-    pub fn add(self, l: FineLoc) -> FineLoc {
-        let offsets = (self.offsets.0 + l.offsets.0,
-                       self.offsets.1 + l.offsets.1);
-        FineLoc::new(self.base.add(l.base), offsets)
-    }
-
-    pub fn sub(self, l: FineLoc) -> FineLoc {
-        let offsets = (self.offsets.0 - l.offsets.0,
-                       self.offsets.1 - l.offsets.1);
-        FineLoc::new(self.base.sub(l.base), offsets)
-    }
-
     pub fn as_coords(self) -> (f64,f64) {
         (self.base.x as f64 + self.offsets.0,
          self.base.y as f64 + self.offsets.1)
@@ -183,13 +241,31 @@ impl FineLoc {
     // }
 }
 
+impl Add for FineLoc {
+    type Output = FineLoc;
+    fn add(self, l: FineLoc) -> FineLoc {
+        let offsets = (self.offsets.0 + l.offsets.0,
+                       self.offsets.1 + l.offsets.1);
+        FineLoc::new(self.base + l.base, offsets)
+    }
+}
+
+impl Sub for FineLoc {
+    type Output = FineLoc;
+    fn sub(self, l: FineLoc) -> FineLoc {
+        let offsets = (self.offsets.0 - l.offsets.0,
+                       self.offsets.1 - l.offsets.1);
+        FineLoc::new(self.base - l.base, offsets)
+    }
+}
+
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub enum RouteResult {
     Complete(Loc),
     InProgress(TileRoute),
 }
 
-#[derive(PartialEq, Copy, Clone, Debug)]
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct TileRoute {
     pub start: Loc,
     dir: Dir,
@@ -203,6 +279,14 @@ impl TileRoute {
     pub fn dest(self) -> Loc {
         self.start.adj(self.dir)
     }
+    /// Distance covered by the whole route, in tiles
+    pub fn length(self) -> f64 {
+        if self.dir.is_diagonal() {
+            std::f64::consts::SQRT_2
+        } else {
+            1.0
+        }
+    }
     pub fn advance(mut self, delta: f64) -> RouteResult {
         self.progress += delta;
         if self.progress >= 1.0 {
@@ -215,17 +299,7 @@ impl TileRoute {
         self.progress
     }
     pub fn as_fineloc(self) -> FineLoc {
-        let (xo,yo) = if self.dir == Dir::north() {
-            (0.0, 0.0 - self.progress)
-        } else if self.dir == Dir::south() {
-            (0.0, self.progress)
-        } else if self.dir == Dir::east() {
-            (self.progress, 0.0)
-        } else if self.dir == Dir::west() {
-            (0.0 - self.progress, 0.0)
-        } else {
-            panic!("Weird Dir value: {:?}", self.dir)
-        };
-        FineLoc::new(self.start, (xo,yo))
+        let (x,y) = self.dir.offset();
+        FineLoc::new(self.start, (x as f64 * self.progress, y as f64 * self.progress))
     }
 }
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+
+use crate::geometry::*;
+use crate::game::TRACE_TICK;
+use crate::save::*;
+
+/// Any further between two places in a trace than this, and the run
+/// must have gone through a teleporter rather than walked
+const JUMP: f64 = 1.0;
+
+/// The best run through a level, to race against
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Ghost {
+    pub score: u32,
+    /// Seconds the run took
+    #[serde(default = "unknown_time")]
+    pub time: f64,
+    /// Where the run was, every TRACE_TICK seconds
+    trace: Vec<(f64, f64)>,
+}
+
+/// Ghosts kept before their times were, which any tie beats
+fn unknown_time() -> f64 {
+    f64::INFINITY
+}
+
+impl Ghost {
+    pub fn new(score: u32, time: f64, trace: &[(f64, f64)]) -> Ghost {
+        Ghost{score, time, trace: trace.to_vec()}
+    }
+    /// Where the ghost is some seconds into its run, and which way it
+    /// is facing.  Once its run is over, it waits where it finished.
+    pub fn at(&self, time: f64) -> Option<((f64, f64), Dir)> {
+        let last = self.trace.len().checked_sub(1)?;
+        let pos = (time / TRACE_TICK).max(0.0);
+        let i = (pos.floor() as usize).min(last);
+        let (a, b) = (self.trace[i], self.trace[(i + 1).min(last)]);
+        let jumped = (b.0 - a.0).abs() > JUMP || (b.1 - a.1).abs() > JUMP;
+        let f = if jumped { 0.0 } else { (pos - i as f64).min(1.0) };
+        let here = (a.0 + (b.0 - a.0) * f, a.1 + (b.1 - a.1) * f);
+        Some((here, self.facing(i)))
+    }
+    /// The way the ghost last moved, as of the ith place in its trace
+    fn facing(&self, i: usize) -> Dir {
+        let sign = |d: f64| if d > 1e-6 { 1 } else if d < -1e-6 { -1 } else { 0 };
+        let end = (i + 2).min(self.trace.len());
+        self.trace[..end].windows(2).rev()
+            .find_map(|w| Dir::from_offset((sign(w[1].0 - w[0].0), sign(w[1].1 - w[0].1))))
+            .unwrap_or_else(Dir::south)
+    }
+}
+
+/// The best run through each level that has the same maze every time,
+/// kept between games.  Levels go by the key from `Pack::ghost_key`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Ghosts {
+    #[serde(default)]
+    level: BTreeMap<String, Ghost>,
+}
+
+impl Ghosts {
+    pub fn path() -> PathBuf {
+        data_path("ghosts.toml")
+    }
+    /// Read the ghosts, starting afresh if none have been saved
+    pub fn load() -> io::Result<Ghosts> {
+        Ok(read_toml(&Self::path())?.unwrap_or_default())
+    }
+    pub fn save(&self) -> io::Result<()> {
+        write_toml(&Self::path(), self)
+    }
+    pub fn get(&self, key: &str) -> Option<&Ghost> {
+        self.level.get(key)
+    }
+    /// Keep a run as a level's ghost if it beats the one there.  As
+    /// with high scores, ties go to the quicker run.  Gives whether
+    /// it was kept.
+    pub fn offer(&mut self, key: &str, ghost: Ghost) -> bool {
+        match self.level.get(key) {
+            Some(g) if (g.score, -g.time) >= (ghost.score, -ghost.time) => false,
+            _ => {
+                self.level.insert(key.to_string(), ghost);
+                true
+            },
+        }
+    }
+}
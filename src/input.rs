@@ -0,0 +1,146 @@
+use crate::geometry::*;
+
+/// Which way the player wants to go, built up from directional keys
+/// being pressed and let go.
+#[derive(Clone, Debug, Default)]
+pub struct Intent {
+    /// Straight directions currently held down, the most recently
+    /// pressed last
+    held: Vec<Dir>,
+    /// The last direction pressed partway between tiles, kept until
+    /// it can be acted on even if its key has already been let go.  A
+    /// quick tap between tiles turns at the next tile, or at the first
+    /// one after that with room to turn, unless a junction is passed
+    /// on the way.
+    buffered: Option<Dir>,
+}
+
+impl Intent {
+    pub fn new() -> Intent {
+        Intent::default()
+    }
+    /// Note a direction's key going down.  Only a press made partway
+    /// along a route is kept for the next tile; from a standstill,
+    /// the keys held are all that matter.
+    pub fn press(&mut self, dir: Dir, mid_route: bool) {
+        self.held.retain(|d| *d != dir);
+        self.held.push(dir);
+        if mid_route {
+            self.buffered = Some(dir);
+        }
+    }
+    pub fn release(&mut self, dir: Dir) {
+        self.held.retain(|d| *d != dir);
+    }
+    /// The direction being held, if any.  The most recent key wins,
+    /// and holding an earlier one at right angles to it as well makes
+    /// for a diagonal.
+    pub fn intended(&self) -> Option<Dir> {
+        let last = *self.held.last()?;
+        let diagonal = self.held.iter().rev().skip(1)
+            .find_map(|d| Dir::between(last, *d));
+        Some(diagonal.unwrap_or(last))
+    }
+    /// Directions worth trying at a tile, best first: a buffered turn
+    /// whose key was tapped and let go, then what is being held, then
+    /// each held key on its own from the most recent back.  Holding
+    /// on to the way you're going while pressing a turn keeps going
+    /// until the turn opens up, or goes diagonally if it can.
+    pub fn choices(&self) -> Vec<Dir> {
+        let mut choices = Vec::new();
+        let tapped = self.buffered.filter(|d| !self.held.contains(d));
+        let all = tapped.iter()
+            .chain(self.intended().iter())
+            .chain(self.held.iter().rev())
+            .cloned()
+            .collect::<Vec<Dir>>();
+        for d in all {
+            if !choices.contains(&d) {
+                choices.push(d);
+            }
+        }
+        choices
+    }
+    /// Note that a move was made in dir, using up the buffered turn
+    /// if that's what it was
+    pub fn moved(&mut self, dir: Dir) {
+        if self.buffered == Some(dir) {
+            self.buffered = None;
+        }
+    }
+    /// Note a move made in dir from a junction.  A buffered turn that
+    /// couldn't be taken there is dropped rather than taken somewhere
+    /// further on.
+    pub fn passed_junction(&mut self, dir: Dir) {
+        if self.buffered != Some(dir) {
+            self.buffered = None;
+        }
+    }
+    /// Forget any buffered turn, as when coming to a stop
+    pub fn clear_buffer(&mut self) {
+        self.buffered = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The first of the choices that's open, as the game picks at a
+    /// tile
+    fn pick(intent: &Intent, open: &[Dir]) -> Option<Dir> {
+        intent.choices().into_iter().find(|d| open.contains(d))
+    }
+
+    #[test]
+    fn tap_mid_route_turns_at_next_tile() {
+        let mut intent = Intent::new();
+        intent.press(Dir::north(), false);
+        intent.press(Dir::east(), true);
+        intent.release(Dir::east());
+        assert_eq!(pick(&intent, &[Dir::north(), Dir::east()]), Some(Dir::east()));
+        intent.moved(Dir::east());
+        assert_eq!(pick(&intent, &[Dir::north(), Dir::east()]), Some(Dir::north()));
+    }
+
+    #[test]
+    fn tap_is_dropped_at_junction_it_cant_take() {
+        let mut intent = Intent::new();
+        intent.press(Dir::north(), false);
+        intent.press(Dir::east(), true);
+        intent.release(Dir::east());
+        // A junction to the west only, so the turn east can't be taken
+        assert_eq!(pick(&intent, &[Dir::north(), Dir::west()]), Some(Dir::north()));
+        intent.passed_junction(Dir::north());
+        intent.moved(Dir::north());
+        assert_eq!(pick(&intent, &[Dir::north(), Dir::east()]), Some(Dir::north()));
+    }
+
+    #[test]
+    fn tap_goes_before_held_key() {
+        let mut intent = Intent::new();
+        intent.press(Dir::north(), false);
+        intent.press(Dir::west(), true);
+        intent.release(Dir::west());
+        assert_eq!(intent.intended(), Some(Dir::north()));
+        assert_eq!(intent.choices(), vec![Dir::west(), Dir::north()]);
+        // Walls to the west for now, so the tap waits while the held
+        // key carries on
+        assert_eq!(pick(&intent, &[Dir::north()]), Some(Dir::north()));
+        intent.moved(Dir::north());
+        assert_eq!(pick(&intent, &[Dir::north(), Dir::west()]), Some(Dir::west()));
+    }
+
+    #[test]
+    fn two_held_keys_go_diagonally() {
+        let mut intent = Intent::new();
+        intent.press(Dir::north(), false);
+        intent.press(Dir::east(), false);
+        assert_eq!(intent.intended(), Some(Dir::ne()));
+        assert_eq!(intent.choices(), vec![Dir::ne(), Dir::east(), Dir::north()]);
+        // Blocked diagonally, the most recent key is tried next
+        assert_eq!(pick(&intent, &[Dir::north(), Dir::east()]), Some(Dir::east()));
+        intent.release(Dir::north());
+        assert_eq!(intent.intended(), Some(Dir::east()));
+    }
+}
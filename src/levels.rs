@@ -0,0 +1,257 @@
+use serde::Deserialize;
+
+use std::io;
+use std::path::Path;
+
+use crate::battery::*;
+use crate::light::Optics;
+use crate::mazes::*;
+use crate::mazes::maze_gen::Params;
+use crate::save::invalid;
+
+/// A level as written in a pack file.  It takes its maze from either
+/// a text file or the generator, but not both.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LevelSpec {
+    name: String,
+    /// Text file to read the maze from, relative to the pack file
+    file: Option<String>,
+    /// Settings for generating the maze instead
+    generate: Option<Params>,
+    /// Charge the battery starts with, as a percentage
+    #[serde(default = "full")]
+    battery: f64,
+    /// How fast the battery drains, compared to normal
+    #[serde(default = "normal")]
+    drain: f64,
+    /// How the light fades as the battery runs down: the charge is
+    /// raised to this power, so 1 fades in step with it
+    #[serde(default = "normal")]
+    curve: f64,
+    /// Charge below which the light starts to flicker
+    #[serde(default = "flicker")]
+    flicker_below: f64,
+    /// Charge at which the light goes out for good
+    #[serde(default = "empty")]
+    empty: f64,
+    /// Seconds a good run through the level should take
+    par: Option<f64>,
+    /// How the flashlight shines
+    #[serde(default)]
+    flashlight: Optics,
+    /// Show the goal on the minimap from the start, for an easier
+    /// level
+    #[serde(default)]
+    minimap_goal: bool,
+}
+
+fn full() -> f64 {
+    100.0
+}
+
+fn normal() -> f64 {
+    1.0
+}
+
+fn flicker() -> f64 {
+    BatteryConfig::default().flicker_below
+}
+
+fn empty() -> f64 {
+    BatteryConfig::default().empty
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PackSpec {
+    name: Option<String>,
+    level: Vec<LevelSpec>,
+}
+
+/// Where a level's maze comes from
+#[derive(Clone, Debug)]
+pub enum MazeSource {
+    /// The same maze every time, read from a file
+    Fixed(Maze),
+    /// A new maze each time the level is started
+    Generated(Params),
+}
+
+#[derive(Clone, Debug)]
+pub struct Level {
+    pub name: String,
+    pub source: MazeSource,
+    pub battery: f64,
+    pub drain: f64,
+    pub curve: Curve,
+    pub flicker_below: f64,
+    pub empty: f64,
+    pub par: Option<f64>,
+    pub flashlight: Optics,
+    pub minimap_goal: bool,
+}
+
+impl Level {
+    /// Make a maze to play this level in, using the seed given if it
+    /// needs one
+    pub fn maze(&self, seed: u64) -> Maze {
+        match &self.source {
+            MazeSource::Fixed(m) => m.clone(),
+            MazeSource::Generated(p) => maze_gen::generate_locked(&Params{
+                seed: self.maze_seed(seed),
+                ..*p
+            }),
+        }
+    }
+    /// The seed the generator makes this level's maze from, or None
+    /// for a maze read from a file.  A generated level that doesn't
+    /// set its own seed uses the one given, folded down to the 32
+    /// bits the generator takes.
+    pub fn maze_seed(&self, seed: u64) -> Option<u32> {
+        match &self.source {
+            MazeSource::Fixed(_) => None,
+            MazeSource::Generated(p) => Some(p.seed.unwrap_or((seed ^ (seed >> 32)) as u32)),
+        }
+    }
+    /// How the flashlight's battery behaves on this level
+    pub fn battery_config(&self) -> BatteryConfig {
+        let normal = BatteryConfig::default();
+        BatteryConfig{
+            drain_moving: normal.drain_moving * self.drain,
+            drain_still: normal.drain_still * self.drain,
+            curve: self.curve,
+            flicker_below: self.flicker_below,
+            empty: self.empty,
+            start: self.battery,
+        }
+    }
+}
+
+/// A campaign of levels, played one after the other
+#[derive(Clone, Debug)]
+pub struct Pack {
+    pub name: Option<String>,
+    pub levels: Vec<Level>,
+}
+
+impl Default for Pack {
+    /// A single generated maze, for when there is no pack file
+    fn default() -> Pack {
+        Pack{name: None, levels: vec![Level{
+            name: "The maze".to_string(),
+            source: MazeSource::Generated(Params{doors: 2, ..Params::default()}),
+            battery: full(),
+            drain: normal(),
+            curve: Curve::Linear,
+            flicker_below: flicker(),
+            empty: empty(),
+            par: None,
+            flashlight: Optics::default(),
+            minimap_goal: false,
+        }]}
+    }
+}
+
+/// Say what's wrong with a level's settings, if anything
+fn check_level(l: &LevelSpec) -> Result<(), String> {
+    if let Some(p) = &l.generate {
+        p.check()?;
+    }
+    if !(l.battery > 0.0 && l.battery <= 100.0) {
+        return Err(format!("battery must be over 0 and at most 100, not {}", l.battery));
+    }
+    if !(l.drain > 0.0 && l.drain.is_finite()) {
+        return Err(format!("drain must be over 0, not {}", l.drain));
+    }
+    if !(l.curve > 0.0 && l.curve.is_finite()) {
+        return Err(format!("curve must be over 0, not {}", l.curve));
+    }
+    if !(l.flicker_below >= 0.0 && l.flicker_below <= 100.0) {
+        return Err(format!("flicker_below must be from 0 to 100, not {}", l.flicker_below));
+    }
+    if !(l.empty >= 0.0 && l.empty < l.battery) {
+        return Err(format!("empty must be at least 0 and under the battery's {}, not {}", l.battery, l.empty));
+    }
+    if let Some(par) = l.par {
+        if !(par > 0.0 && par.is_finite()) {
+            return Err(format!("par must be over 0, not {}", par));
+        }
+    }
+    l.flashlight.check()
+}
+
+/// The key for a generated maze, which is the same in any pack
+fn generated_key(p: &Params, seed: u32) -> String {
+    format!(
+        "generated size {} twisty {} swirly {} branchy {} doors {} seed {}",
+        p.size, p.twisty, p.swirly, p.branchy, p.doors, seed,
+    )
+}
+
+impl Pack {
+    /// What to file a level's ghost under, given the seed its maze
+    /// was generated from, if it was.  A generated level without a
+    /// seed of its own goes by the seed it was played with, so its
+    /// ghost comes back whenever the same maze does.
+    pub fn ghost_key(&self, level: usize, maze_seed: Option<u32>) -> Option<String> {
+        match (&self.levels[level].source, maze_seed) {
+            (MazeSource::Fixed(_), _) => Some(self.score_key(level)),
+            (MazeSource::Generated(p), Some(seed)) => Some(generated_key(p, seed)),
+            (MazeSource::Generated(_), None) => None,
+        }
+    }
+    /// What to file high scores for a level under.  A generated
+    /// level with a seed is the same maze in any pack, so it goes by
+    /// its settings; any other level goes by its pack and name.
+    pub fn score_key(&self, level: usize) -> String {
+        let l = &self.levels[level];
+        match &l.source {
+            MazeSource::Generated(p @ Params{seed: Some(seed), ..}) => generated_key(p, *seed),
+            _ => format!("{}: {}", self.name.as_deref().unwrap_or("default"), l.name),
+        }
+    }
+    /// Read a pack file.  Mazes from text files are read in right
+    /// away, so that any problem with them shows up here rather than
+    /// partway through the campaign.
+    pub fn load(fname: &str) -> io::Result<Pack> {
+        let contents = std::fs::read_to_string(fname)?;
+        let spec: PackSpec = toml::from_str(&contents).map_err(invalid)?;
+        let dir = Path::new(fname).parent().unwrap_or_else(|| Path::new(""));
+        let mut levels = Vec::new();
+        for l in spec.level {
+            check_level(&l).map_err(|e| invalid(format!("level \"{}\": {}", l.name, e)))?;
+            let name = l.name;
+            let source = match (l.file, l.generate) {
+                (Some(f), None) => {
+                    let path = dir.join(&f);
+                    let maze = parse_maze(&path.to_string_lossy()).map_err(|e| {
+                        invalid(format!("level \"{}\": {}: {}", name, f, e))
+                    })?;
+                    MazeSource::Fixed(maze)
+                },
+                (None, Some(p)) => MazeSource::Generated(p),
+                _ => return Err(invalid(format!(
+                    "level \"{}\" needs either a file or generate settings, but not both",
+                    name,
+                ))),
+            };
+            levels.push(Level{
+                name,
+                source,
+                battery: l.battery,
+                drain: l.drain,
+                curve: if l.curve == 1.0 { Curve::Linear } else { Curve::Power(l.curve) },
+                flicker_below: l.flicker_below,
+                empty: l.empty,
+                par: l.par,
+                flashlight: l.flashlight,
+                minimap_goal: l.minimap_goal,
+            });
+        }
+        if levels.is_empty() {
+            return Err(invalid("a pack needs at least one level"));
+        }
+        Ok(Pack{name: spec.name, levels})
+    }
+}
@@ -0,0 +1,24 @@
+extern crate libc;
+extern crate gfx_device_gl;
+extern crate piston;
+extern crate piston_window;
+extern crate rand;
+
+pub mod geometry;
+pub mod mazes;
+pub mod light;
+pub mod solver;
+pub mod bench;
+pub mod battery;
+pub mod input;
+pub mod controls;
+pub mod game;
+pub mod levels;
+pub mod score;
+pub mod save;
+pub mod replay;
+pub mod ghost;
+pub mod font;
+pub mod controller;
+pub mod bots;
+
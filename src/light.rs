@@ -1,13 +1,12 @@
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 
 use crate::geometry::*;
 use crate::mazes::*;
 
 pub type Lum = f64;
 
-// Vals come from c-maze
-const DIVP: Lum = 2.0;
-const DIVS: Lum = 3.0;
+/// Red, green and blue parts of some light
+pub type Rgb = [Lum; 3];
 
 // Derived from c-maze full battery value (8000 * 4 / 1600)
 pub const INIT_LIGHT: f64 = 20.0;
@@ -18,76 +17,575 @@ pub const DARK1_LIGHT: f64 = 3.0;
 // In c-maze, light values of < 1 are total dark
 pub const DARK2_LIGHT: f64 = 1.0;
 
+// A lamp shines about as bright as a half-charged flashlight
+pub const LAMP_LIGHT: f64 = 10.0;
+
+// Light at which tiles are drawn at full brightness
+pub const FULL_LIGHT: f64 = 6.0;
+
+// The flashlight gives off a warm white
+pub const FLASHLIGHT_COLOR: Rgb = [1.0, 0.92, 0.75];
+
+// Lamps burn orange, like torches
+pub const LAMP_COLOR: Rgb = [1.0, 0.6, 0.3];
+
+// Light has to dim by at least this much per tile, or it would never
+// dim out of sight
+pub const MIN_FALLOFF: Lum = 1.01;
+
+/// Ways of working out where light reaches
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Model {
+    /// Rays that stop at the first wall they hit, as in c-maze.
+    /// Narrow corridors are lit well, but open rooms only patchily.
+    Rays,
+    /// Recursive shadowcasting, which lights every tile in line of
+    /// sight of the source, dimming with distance
+    Shadowcast,
+}
+
+impl Model {
+    /// Switch to the other model
+    pub fn toggle(self) -> Model {
+        match self {
+            Model::Rays => Model::Shadowcast,
+            Model::Shadowcast => Model::Rays,
+        }
+    }
+}
+
+/// Settings for the flashlight's beam
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Beam {
+    /// A wide cone at low power, easy on the battery
+    Wide,
+    /// The classic narrow cone at full power
+    Narrow,
+}
+
+impl Beam {
+    /// Switch to the other beam
+    pub fn toggle(self) -> Beam {
+        match self {
+            Beam::Wide => Beam::Narrow,
+            Beam::Narrow => Beam::Wide,
+        }
+    }
+    /// How hard the beam works the battery, compared to the classic
+    pub fn load(self) -> f64 {
+        match self {
+            Beam::Wide => 0.6,
+            Beam::Narrow => 1.0,
+        }
+    }
+    /// Make the flashlight's source with this beam
+    pub fn source(self, optics: &Optics, loc: Loc, dir: Dir, battery: f64) -> Source {
+        let (power, half) = match self {
+            Beam::Wide => (INIT_LIGHT * 0.5, optics.wide),
+            Beam::Narrow => (INIT_LIGHT, optics.narrow),
+        };
+        Source::new(loc, dir, power * (battery / 100.0), Shape::Cone(half))
+            .with_falloff(optics.falloff)
+            .with_color(FLASHLIGHT_COLOR)
+    }
+}
+
+/// How the flashlight shines on a level: the half-angles of its
+/// cones in degrees, and how quickly its light dims
+#[derive(PartialEq, Copy, Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Optics {
+    pub narrow: f64,
+    pub wide: f64,
+    pub falloff: Falloff,
+}
+
+impl Default for Optics {
+    fn default() -> Optics {
+        Optics{narrow: 45.0, wide: 90.0, falloff: Falloff::default()}
+    }
+}
+
+impl Optics {
+    /// Say what's wrong with the settings, if anything
+    pub fn check(&self) -> Result<(), String> {
+        for half in [self.narrow, self.wide].iter() {
+            if !(*half > 0.0 && *half <= 180.0) {
+                return Err(format!("a cone's half-angle must be over 0 and at most 180, not {}", half));
+            }
+        }
+        self.falloff.check()
+    }
+}
+
+/// The spread of light from a source
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Shape {
+    /// Shines all the way around
+    Radius,
+    /// Shines ahead, spreading out to the given angle on either side,
+    /// in degrees.  A 45 degree cone is the classic c-maze flashlight.
+    Cone(f64),
+    /// Shines straight ahead without spreading at all
+    Beam,
+}
+
+/// How quickly light dims as it travels.  Light is divided by
+/// `ahead` for each tile it goes straight on, and by `side` for each
+/// tile it spreads out sideways.  Both have to be over 1, or the
+/// light would never dim.
+#[derive(PartialEq, Copy, Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Falloff {
+    pub ahead: Lum,
+    pub side: Lum,
+}
+
+impl Default for Falloff {
+    /// Vals come from c-maze
+    fn default() -> Falloff {
+        Falloff{ahead: 2.0, side: 3.0}
+    }
+}
+
+impl Falloff {
+    /// Say what's wrong with the divisors, if anything
+    pub fn check(&self) -> Result<(), String> {
+        if self.ahead > 1.0 && self.side > 1.0 {
+            Ok(())
+        } else {
+            Err(format!("falloff must be over 1, not ahead {} and side {}", self.ahead, self.side))
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
-enum SourceKind {
+enum RayKind {
     Primary,
     Left,
     Right,
 }
 
+/// A single ray of light on its way through the maze
+#[derive(Copy, Clone, Debug)]
+struct Ray {
+    power: Lum,
+    dir: Dir,
+    loc: Loc,
+    kind: RayKind,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Source {
     power: Lum,
     dir: Dir,
     loc: Loc,
-    kind: SourceKind,
+    shape: Shape,
+    falloff: Falloff,
+    color: Rgb,
 }
 
 impl Source {
+    /// Make a source of plain white light
+    pub fn new(loc: Loc, dir: Dir, power: Lum, shape: Shape) -> Source {
+        Source{power, dir, loc, shape, falloff: Falloff::default(), color: [1.0; 3]}
+    }
+    /// The player's flashlight, dimming as the battery runs down
     pub fn mk_source(loc: Loc, dir: Dir, battery: f64) -> Source {
-        Source{power: INIT_LIGHT * (battery / 100.0), dir, loc, kind: SourceKind::Primary}
-    }
-}
-
-pub fn illuminate(maze: &Maze, source: &Source, map: &mut HashMap<Loc,Lum>) {
-    map.insert(source.loc, source.power);
-    if source.power >= 1.0 && maze.map.get(&source.loc) == Some(&Tile::Floor) {
-        match source.kind {
-            SourceKind::Primary => {
-                let fsrc = Source{
-                    power: source.power / DIVP,
-                    dir: source.dir,
-                    loc: source.loc.adj(source.dir),
-                    kind: SourceKind::Primary,
-                };
-                illuminate(maze, &fsrc, map);
-                let ldir = source.dir.turn(&Angle::a45().reverse());
-                let lsrc = Source{
-                    power: source.power / DIVS,
-                    dir: ldir,
-                    loc: source.loc.adj(ldir),
-                    kind: SourceKind::Left,
-                };
-                illuminate(maze, &lsrc, map);
-                let rdir = source.dir.turn(&Angle::a45());
-                let rsrc = Source{
-                    power: source.power / DIVS,
-                    dir: rdir,
-                    loc: source.loc.adj(rdir),
-                    kind: SourceKind::Right,
-                };
-                illuminate(maze, &rsrc, map);
+        Source::new(loc, dir, INIT_LIGHT * (battery / 100.0), Shape::Cone(45.0))
+            .with_color(FLASHLIGHT_COLOR)
+    }
+    /// A fixed lamp, shining in every direction
+    pub fn mk_lamp(loc: Loc) -> Source {
+        Source::new(loc, Dir::north(), LAMP_LIGHT, Shape::Radius).with_color(LAMP_COLOR)
+    }
+    /// Change how quickly the light dims.  Divisors too small to
+    /// ever dim the light are raised to MIN_FALLOFF.
+    pub fn with_falloff(mut self, falloff: Falloff) -> Source {
+        self.falloff = Falloff{
+            ahead: falloff.ahead.max(MIN_FALLOFF),
+            side: falloff.side.max(MIN_FALLOFF),
+        };
+        self
+    }
+    /// Tint the light.  Each part of the color scales the power, so
+    /// 1.0 in all three is white light at full strength.
+    pub fn with_color(mut self, color: Rgb) -> Source {
+        self.color = color;
+        self
+    }
+    /// How far the light can get before it dims out of sight
+    pub fn reach(&self) -> Int {
+        if self.power < 1.0 {
+            0
+        } else {
+            (self.power.ln() / self.falloff.ahead.ln()).ceil() as Int
+        }
+    }
+}
+
+/// A square grid of light values centered on some location.  Light
+/// falling outside of the grid is ignored.
+///
+/// Alongside the overall light at each spot, the grid keeps the mix
+/// of colors making it up.  It also keeps track of the rectangle of
+/// it that has been lit, so that a small light in a big grid is cheap
+/// to clear and combine.
+#[derive(Clone, Debug)]
+pub struct LightGrid {
+    corner: Loc,
+    radius: Int,
+    lums: Vec<Lum>,
+    tints: Vec<Rgb>,
+    // Lit rectangle, in grid coordinates (empty when lo > hi)
+    lo: Loc,
+    hi: Loc,
+}
+
+impl LightGrid {
+    pub fn new(radius: Int) -> LightGrid {
+        let size = radius * 2 + 1;
+        LightGrid{
+            corner: Loc{x: -radius, y: -radius},
+            radius,
+            lums: vec![0.0; (size * size) as usize],
+            tints: vec![[0.0; 3]; (size * size) as usize],
+            lo: Loc{x: size, y: size},
+            hi: Loc{x: -1, y: -1},
+        }
+    }
+    fn size(&self) -> Int {
+        self.radius * 2 + 1
+    }
+    pub fn center(&self) -> Loc {
+        self.corner + Loc{x: self.radius, y: self.radius}
+    }
+    /// Put out all the light and move the grid to a new center
+    pub fn reset(&mut self, center: Loc) {
+        let size = self.size();
+        for y in self.lo.y..=self.hi.y {
+            for x in self.lo.x..=self.hi.x {
+                let i = (y * size + x) as usize;
+                self.lums[i] = 0.0;
+                self.tints[i] = [0.0; 3];
             }
-            SourceKind::Left => {
-                let dir = source.dir.turn(&Angle::a45().reverse());
-                let src = Source {
-                    power: source.power / DIVS,
-                    dir,
-                    loc: source.loc.adj(dir),
-                    kind: SourceKind::Left,
-                };
-                illuminate(&maze, &src, map);
-            },
-            SourceKind::Right => {
-                let dir = source.dir.turn(&Angle::a45());
-                let src = Source {
-                    power: source.power / DIVS,
-                    dir,
-                    loc: source.loc.adj(dir),
-                    kind: SourceKind::Right,
-                };
-                illuminate(&maze, &src, map);
+        }
+        self.corner = center - Loc{x: self.radius, y: self.radius};
+        self.lo = Loc{x: size, y: size};
+        self.hi = Loc{x: -1, y: -1};
+    }
+    /// Mark a spot, in grid coordinates, as lit
+    fn touch(&mut self, at: Loc) {
+        self.lo = Loc{x: self.lo.x.min(at.x), y: self.lo.y.min(at.y)};
+        self.hi = Loc{x: self.hi.x.max(at.x), y: self.hi.y.max(at.y)};
+    }
+    fn slot(&self, loc: Loc) -> Option<(Loc,usize)> {
+        let size = self.size();
+        let at = loc - self.corner;
+        if at.x >= 0 && at.x < size && at.y >= 0 && at.y < size {
+            Some((at, (at.y * size + at.x) as usize))
+        } else {
+            None
+        }
+    }
+    /// Light at a location, which is 0 anywhere off the grid
+    pub fn get(&self, loc: Loc) -> Lum {
+        match self.slot(loc) {
+            Some((_, i)) => self.lums[i],
+            None => 0.0,
+        }
+    }
+    /// Colored light at a location
+    pub fn get_rgb(&self, loc: Loc) -> Rgb {
+        match self.slot(loc) {
+            Some((_, i)) => self.tints[i],
+            None => [0.0; 3],
+        }
+    }
+    /// Every location on the grid with at least the given light
+    pub fn lit(&self, min: Lum) -> impl Iterator<Item = Loc> + '_ {
+        let size = self.size();
+        (self.lo.y..=self.hi.y)
+            .flat_map(move |y| (self.lo.x..=self.hi.x).map(move |x| Loc{x, y}))
+            .filter(move |at| self.lums[(at.y * size + at.x) as usize] >= min)
+            .map(move |at| at + self.corner)
+    }
+    /// Raise the light at a location to at least the given value
+    fn brighten(&mut self, loc: Loc, lum: Lum) {
+        if let Some((at, i)) = self.slot(loc) {
+            if lum > self.lums[i] {
+                self.lums[i] = lum;
+                self.touch(at);
+            }
+        }
+    }
+    /// Add the light from another grid with the same center and
+    /// radius, scaled by some factor and tinted with a color.  Only
+    /// the overall light of the other grid is used, not its colors.
+    fn add_scaled(&mut self, other: &LightGrid, factor: f64, color: Rgb) {
+        if other.lo.x > other.hi.x {
+            return;
+        }
+        let size = self.size();
+        for y in other.lo.y..=other.hi.y {
+            for x in other.lo.x..=other.hi.x {
+                let i = (y * size + x) as usize;
+                let lum = other.lums[i] * factor;
+                self.lums[i] += lum;
+                for (tint, c) in self.tints[i].iter_mut().zip(color.iter()) {
+                    *tint += lum * c;
+                }
+            }
+        }
+        self.touch(other.lo);
+        self.touch(other.hi);
+    }
+}
+
+/// A row of an octant still to be scanned by the shadowcaster,
+/// between two slopes
+#[derive(Copy, Clone, Debug)]
+struct Span {
+    row: Int,
+    start: f64,
+    end: f64,
+}
+
+/// Buffers for lighting up the area around the player, kept from
+/// frame to frame so that nothing needs allocating once they have
+/// grown to size.
+pub struct Lighting {
+    lums: LightGrid,
+    /// Light from the player's own flashlight alone
+    own: LightGrid,
+    scratch: LightGrid,
+    rays: Vec<Ray>,
+    spans: Vec<Span>,
+    lamps: Vec<Loc>,
+}
+
+impl Lighting {
+    /// Make buffers covering `radius` tiles either side of the center
+    pub fn new(maze: &Maze, radius: Int) -> Lighting {
+        Lighting{
+            lums: LightGrid::new(radius),
+            own: LightGrid::new(radius),
+            scratch: LightGrid::new(radius),
+            rays: Vec::new(),
+            spans: Vec::new(),
+            lamps: maze.lamps(),
+        }
+    }
+    /// Put out all the light and move to a new center
+    pub fn reset(&mut self, center: Loc) {
+        self.lums.reset(center);
+        self.own.reset(center);
+    }
+    /// Light at a location
+    pub fn lum(&self, loc: Loc) -> Lum {
+        self.lums.get(loc)
+    }
+    /// Colored light at a location
+    pub fn rgb(&self, loc: Loc) -> Rgb {
+        self.lums.get_rgb(loc)
+    }
+    /// Light at a location from the flashlight alone, leaving out
+    /// lamps
+    pub fn own_lum(&self, loc: Loc) -> Lum {
+        self.own.get(loc)
+    }
+    /// Every location with enough light to be seen
+    pub fn visible(&self) -> impl Iterator<Item = Loc> + '_ {
+        self.lums.lit(DARK2_LIGHT)
+    }
+    /// Add the light from the flashlight, scaled by some factor.
+    /// Light from separate sources adds up.
+    pub fn illuminate(&mut self, maze: &Maze, model: Model, source: &Source, factor: f64) {
+        self.shine(maze, model, source, factor);
+        self.own.add_scaled(&self.scratch, factor, source.color);
+    }
+    /// Add the light from any source, leaving it in the scratch grid
+    /// as well
+    fn shine(&mut self, maze: &Maze, model: Model, source: &Source, factor: f64) {
+        self.scratch.reset(self.lums.center());
+        match model {
+            Model::Rays => illuminate_rays(maze, source, &mut self.rays, &mut self.scratch),
+            Model::Shadowcast => illuminate_shadowcast(maze, source, &mut self.spans, &mut self.scratch),
+        }
+        self.lums.add_scaled(&self.scratch, factor, source.color);
+    }
+    /// Add the light from every lamp close enough to reach the grid
+    pub fn illuminate_lamps(&mut self, maze: &Maze, model: Model) {
+        let center = self.lums.center();
+        for i in 0..self.lamps.len() {
+            let source = Source::mk_lamp(self.lamps[i]);
+            let Loc{x, y} = source.loc - center;
+            if x.abs().max(y.abs()) <= self.lums.radius + source.reach() {
+                self.shine(maze, model, &source, 1.0);
+            }
+        }
+    }
+}
+
+/// The source is split into rays fanning out across its shape, and
+/// where rays cross, the brightest one wins.  Primary rays go
+/// straight ahead, and unless the source is a beam they shed rays to
+/// either side at each step, which curl further outwards as they go.
+fn illuminate_rays(maze: &Maze, source: &Source, rays: &mut Vec<Ray>, grid: &mut LightGrid) {
+    let ray = |dir| Ray{power: source.power, dir, loc: source.loc, kind: RayKind::Primary};
+    let spread = source.shape != Shape::Beam;
+    // Rays spread in steps of 45 degrees, so any other cone is cut
+    // down to size from the next one up
+    let clip = match source.shape {
+        Shape::Cone(half) => half % 45.0 != 0.0,
+        _ => false,
+    };
+    rays.clear();
+    match source.shape {
+        Shape::Beam => rays.push(ray(source.dir)),
+        Shape::Cone(half) => {
+            // A 45 degree cone is one spreading ray.  Every further
+            // 45 degrees adds another ray on each side.
+            let steps = ((half / 45.0).ceil() as Int).max(1);
+            for i in (1 - steps)..steps {
+                rays.push(ray(source.dir.turn(&Angle::a45().times(i))));
+            }
+        },
+        Shape::Radius => {
+            for i in 0..DIR_RESOLUTION {
+                rays.push(ray(source.dir.turn(&Angle::a45().times(i))));
+            }
+        },
+    }
+
+    let falloff = source.falloff;
+    let side = |ray: &Ray, kind| {
+        let dir = match kind {
+            RayKind::Left => ray.dir.turn(&Angle::a45().reverse()),
+            _ => ray.dir.turn(&Angle::a45()),
+        };
+        Ray{power: ray.power / falloff.side, dir, loc: ray.loc.adj(dir), kind}
+    };
+    while let Some(ray) = rays.pop() {
+        if !clip || in_shape(source, ray.loc.x - source.loc.x, ray.loc.y - source.loc.y) {
+            grid.brighten(ray.loc, ray.power);
+        }
+        if ray.power < 1.0 || opaque(maze, ray.loc) {
+            continue;
+        }
+        match ray.kind {
+            RayKind::Primary => {
+                rays.push(Ray{
+                    power: ray.power / falloff.ahead,
+                    loc: ray.loc.adj(ray.dir),
+                    ..ray
+                });
+                if spread {
+                    rays.push(side(&ray, RayKind::Left));
+                    rays.push(side(&ray, RayKind::Right));
+                }
             },
+            kind => rays.push(side(&ray, kind)),
+        }
+    }
+}
+
+fn opaque(maze: &Maze, loc: Loc) -> bool {
+    !maze.map.get(&loc).is_some_and(|t| t.transparent())
+}
+
+/// Is the offset (dx,dy) from a source within its shape?
+fn in_shape(source: &Source, dx: Int, dy: Int) -> bool {
+    if dx == 0 && dy == 0 {
+        return true;
+    }
+    let (ox, oy) = source.dir.offset();
+    let dot = (dx * ox + dy * oy) as f64;
+    let cross = (dx * oy - dy * ox) as f64;
+    match source.shape {
+        Shape::Radius => true,
+        Shape::Beam => dot > 0.0 && cross == 0.0,
+        Shape::Cone(half) => {
+            let limit = half.to_radians();
+            // Allow a little slack so that tiles right on the edge
+            // of the cone are included
+            cross.atan2(dot).abs() <= limit + 0.01
+        },
+    }
+}
+
+/// Multipliers that transform the first octant into each of the eight
+/// octants around a source
+const OCTANTS: [(Int,Int,Int,Int); 8] = [
+    (1, 0, 0, 1), (0, 1, 1, 0), (0, -1, 1, 0), (-1, 0, 0, 1),
+    (-1, 0, 0, -1), (0, -1, -1, 0), (0, 1, -1, 0), (1, 0, 0, -1),
+];
+
+/// Every tile in line of sight of the source and within its shape is
+/// lit, with the light divided by `falloff.ahead` for each tile of
+/// distance.  Walls facing the source are lit too.
+///
+/// Each octant is scanned row by row outward from the source.  When
+/// something blocks the light partway along a row, the part of the
+/// next row still in view is queued up as a span of its own.
+fn illuminate_shadowcast(maze: &Maze, source: &Source, spans: &mut Vec<Span>, grid: &mut LightGrid) {
+    let radius = source.reach();
+    let mut visit = |loc: Loc| {
+        let (dx, dy) = (loc.x - source.loc.x, loc.y - source.loc.y);
+        if dx * dx + dy * dy <= radius * radius && in_shape(source, dx, dy) {
+            let dist = ((dx * dx + dy * dy) as f64).sqrt();
+            grid.brighten(loc, source.power / source.falloff.ahead.powf(dist));
+        }
+    };
+    if source.power < 1.0 {
+        return;
+    }
+    visit(source.loc);
+    if opaque(maze, source.loc) {
+        return;
+    }
+
+    for &(xx, xy, yx, yy) in OCTANTS.iter() {
+        spans.clear();
+        spans.push(Span{row: 1, start: 1.0, end: 0.0});
+        while let Some(Span{row, mut start, end}) = spans.pop() {
+            if start < end {
+                continue;
+            }
+            let mut next_start = start;
+            for j in row..=radius {
+                let mut blocked = false;
+                let dy = -j;
+                for dx in -j..=0 {
+                    let l_slope = (dx as f64 - 0.5) / (dy as f64 + 0.5);
+                    let r_slope = (dx as f64 + 0.5) / (dy as f64 - 0.5);
+                    if start < r_slope {
+                        continue;
+                    } else if end > l_slope {
+                        break;
+                    }
+                    let loc = Loc{
+                        x: source.loc.x + dx * xx + dy * xy,
+                        y: source.loc.y + dx * yx + dy * yy,
+                    };
+                    visit(loc);
+                    if blocked {
+                        if opaque(maze, loc) {
+                            next_start = r_slope;
+                        } else {
+                            blocked = false;
+                            start = next_start;
+                        }
+                    } else if opaque(maze, loc) && j < radius {
+                        blocked = true;
+                        spans.push(Span{row: j + 1, start, end: l_slope});
+                        next_start = r_slope;
+                    }
+                }
+                if blocked {
+                    break;
+                }
+            }
         }
     }
 }
@@ -1,19 +1,17 @@
-extern crate libc;
-extern crate gfx_device_gl;
-extern crate piston;
-extern crate piston_window;
-
-pub mod geometry;
-pub mod mazes;
-pub mod light;
-
 use piston_window::*;
-use std::collections::HashMap;
 
-use crate::geometry::*;
-use RouteResult::{Complete,InProgress};
-use crate::mazes::*;
-use crate::light::*;
+use r_maze::{bench, bots, font};
+use r_maze::geometry::*;
+use r_maze::mazes::*;
+use r_maze::light::*;
+use r_maze::controls::*;
+use r_maze::game::*;
+use r_maze::levels::*;
+use r_maze::score::*;
+use r_maze::save::*;
+use r_maze::replay::*;
+use r_maze::ghost::*;
+use r_maze::controller::*;
 
 /// Pixel width (and height) of artsheet tiles
 const ART_SIZE: u32 = 16; // From IOStuff.c in c-maze
@@ -21,11 +19,30 @@ const ART_SIZE: u32 = 16; // From IOStuff.c in c-maze
 /// Number of tiles in the art sheet
 const ART_NUM: u32 = 10;
 
-/// Draw distance
-const DRAW_DIST: isize = 10;
+/// File to read key and gamepad bindings from
+const CONTROLS_FILE: &str = "controls.toml";
+
+/// File to read the campaign's levels from
+const PACK_FILE: &str = "levels.toml";
+
+/// Pixel width (and height) of a tile on the minimap
+const MINIMAP_SCALE: f64 = 4.0;
+
+/// Gap between the minimap and the edges of the window, in pixels
+const MINIMAP_MARGIN: f64 = 8.0;
 
-/// Distance cam falls behind before following
-const CAM_DIST: f64 = 1.0;
+/// Where the text on title, pause and end screens starts, in pixels
+/// from the top of the window
+const SCREEN_TOP: f64 = 80.0;
+
+/// Seconds the level's name stays up when it starts
+const LEVEL_BANNER: f64 = 2.0;
+
+/// How many of a level's best runs the victory screen shows
+const SCORES_SHOWN: usize = 5;
+
+/// How solid the ghost looks, at most
+const GHOST_ALPHA: f32 = 0.45;
 
 
 /// Names for the tiles in the art sheet
@@ -40,6 +57,19 @@ enum Art {
     CSouth,
     CEast,
     CWest,
+    DoorRed,
+    DoorBlue,
+    DoorYellow,
+    KeyRed,
+    KeyBlue,
+    KeyYellow,
+    Teleporter,
+    Lamp,
+    Remembered,
+    CNorthEast,
+    CSouthEast,
+    CSouthWest,
+    CNorthWest,
 }
 
 impl Art {
@@ -54,219 +84,557 @@ impl Art {
         ];
         Image::new().src_rect(rect)
     }
-}
-
-type LocMode = RouteResult;
-
-struct Game {
-    maze: Maze,
-    loc: LocMode,
-    intended_dir: Option<Dir>,
-    speed: f64, // in tiles/sec
-    dir: Dir,
-    camera: FineLoc,
-    battery: f64,
-}
-
-impl Game {
-    /// Make a new game for a maze
-    fn new(maze: Maze) -> Game {
-        let start_loc = maze.start;
-        Game{
-            maze,
-            loc: Complete(start_loc),
-            dir: Dir::south(),
-            speed: 3.0,
-            intended_dir: None,
-            camera: FineLoc::from_loc(start_loc),
-            battery: 100.0,
-        }
-    }
-    fn intend(&mut self, dir: Dir) {
-        self.intended_dir = Some(dir);
-    }
-    fn unintend(&mut self, dir: Dir) {
-        if let Some(d) = self.intended_dir {
-            if d == dir {
-                self.intended_dir = None;
-            }
+    fn door(color: KeyColor) -> Art {
+        match color {
+            KeyColor::Red => Art::DoorRed,
+            KeyColor::Blue => Art::DoorBlue,
+            KeyColor::Yellow => Art::DoorYellow,
         }
     }
-    /// Get tile at loc
-    fn tile_at(&self, loc: Loc) -> Option<Tile> {
-        match self.maze.map.get(&loc) {
-            Some(t) => Some(t.clone()),
-            None => None,
+    fn key(color: KeyColor) -> Art {
+        match color {
+            KeyColor::Red => Art::KeyRed,
+            KeyColor::Blue => Art::KeyBlue,
+            KeyColor::Yellow => Art::KeyYellow,
         }
     }
-    /// Get correct art for character's current state (just depends on
-    /// which direction they're facing).
-    fn c_art(&self) -> Art {
-        match self.dir.as_int() {
+    /// Get correct art for the character facing a direction
+    fn character(dir: Dir) -> Art {
+        match dir.as_int() {
             0 => Art::CNorth,
+            1 => Art::CNorthEast,
             2 => Art::CEast,
+            3 => Art::CSouthEast,
             4 => Art::CSouth,
+            5 => Art::CSouthWest,
             6 => Art::CWest,
+            7 => Art::CNorthWest,
             _ => Art::Error,
         }
     }
-    /// Get tile adjecent to current loc, in given direction
-    fn adj(&self, dir: Dir) -> Option<Tile> {
-        match self.loc {
-            Complete(l) => self.tile_at(l.adj(dir)),
-            InProgress(r) => self.tile_at(r.start.adj(dir)),
+}
+
+/// Color to draw art in unchanged
+const WHITE: types::Color = [1.0; 4];
+
+/// Color to draw a tile in under some light, shading smoothly from
+/// black up to the full art at FULL_LIGHT
+fn tint(rgb: Rgb, gamma: f64) -> types::Color {
+    let shade = |c: Lum| (c / FULL_LIGHT).min(1.0).powf(1.0 / gamma) as f32;
+    [shade(rgb[0]), shade(rgb[1]), shade(rgb[2]), 1.0]
+}
+
+fn render<E>(
+    game: &Game,
+    ghost: Option<&Ghost>,
+    scores: &HighScores,
+    place: Option<usize>,
+    window: &mut PistonWindow,
+    e: E,
+    tilesheet: &Texture<gfx_device_gl::Resources>,
+) where E: piston_window::GenericEvent {
+    window.draw_2d(&e, |c, g, _| {
+        clear([0.0; 4], g);
+        if game.state != State::Title {
+            draw_play(game, ghost, &c, g, tilesheet);
         }
-    }
-    /// Update position if in motion, otherwise set into motion if
-    /// there is intent.
-    fn update(&mut self, dt: f64) {
-        match self.loc {
-            InProgress(route) => {
-                self.loc = route.advance(dt * self.speed);
-            },
-            Complete(loc) => match self.intended_dir {
-                Some(d) => {
-                    self.dir = d;
-                    if self.adj(d) == Some(Tile::Floor) {
-                        self.loc = InProgress(TileRoute::new(loc,d));
+        draw_screen(game, scores, place, &c, g);
+    });
+}
+
+/// Draw the maze around the player, and everything shown over it
+/// while playing
+fn draw_play(game: &Game, ghost: Option<&Ghost>, c: &Context, g: &mut G2d, tilesheet: &Texture<gfx_device_gl::Resources>) {
+    let lighting = &game.lighting;
+    let mut draw_tile_c = |cs: (f64,f64), a: Art, color: types::Color| {
+        let t = c.transform.trans(
+            ART_SIZE as f64 * cs.0,
+            ART_SIZE as f64 * cs.1,
+        );
+        a.image().color(color).draw(tilesheet, &DrawState::default(), t, g);
+    };
+
+    let mut draw_tile = |l: FineLoc, a: Art, color: types::Color| {
+        draw_tile_c(l.as_coords(), a, color);
+    };
+
+    // Draw map tiles
+    let draw_cam = FineLoc::from_loc(Loc{ x: DRAW_DIST, y: DRAW_DIST });
+    let map_cam = game.camera;
+
+    for x in 0..(DRAW_DIST * 2 + 1) {
+        for y in 0..(DRAW_DIST * 2 + 1) {
+            // The point on the screen we are filling in
+            let draw_loc = FineLoc::from_loc(Loc{x,y}) - FineLoc::from_coords(map_cam.get_offsets());
+            // The location in the map we are representing
+            let map_loc = map_cam - (draw_cam - draw_loc);
+            let n = lighting.lum(map_loc.base);
+            let remembered = game.explored.contains(&map_loc.base);
+            if n < DARK2_LIGHT && !remembered {
+                draw_tile(draw_loc, Art::Dark2, WHITE);
+                continue;
+            }
+            // Tiles out of the light, but seen before, are drawn
+            // plainly and then faded out.  In retro mode, dim
+            // tiles are drawn plainly and then shaded over.
+            let color = if n < DARK2_LIGHT || game.retro_shading {
+                WHITE
+            } else {
+                tint(lighting.rgb(map_loc.base), game.gamma)
+            };
+            match game.maze.map.get(&map_loc.base) {
+                Some(Tile::Door(c)) => draw_tile(draw_loc, Art::door(*c), color),
+                Some(t) => {
+                    draw_tile(draw_loc, Art::Floor, color);
+                    if game.maze.goal == map_loc.base {
+                        draw_tile(draw_loc, Art::Goal, color);
+                    }
+                    match t {
+                        Tile::Key(c) => draw_tile(draw_loc, Art::key(*c), color),
+                        Tile::Teleporter(_) => draw_tile(draw_loc, Art::Teleporter, color),
+                        Tile::Lamp => draw_tile(draw_loc, Art::Lamp, color),
+                        _ => (),
                     }
                 },
-                None => (),
-            },
+                None => draw_tile(draw_loc, Art::Wall, color),
+            }
+            if n < DARK2_LIGHT {
+                draw_tile(draw_loc, Art::Remembered, WHITE);
+            } else if game.retro_shading && n < DARK1_LIGHT {
+                draw_tile(draw_loc, Art::Dark1, WHITE);
+            }
         }
     }
-    fn base_loc(&self) -> Loc {
-        match self.loc {
-            Complete(l) => l,
-            InProgress(r) => r.as_fineloc().base,
+
+    // The ghost gives off no light of its own, so it only shows
+    // where the player's flashlight falls on it
+    if let Some((at, dir)) = ghost.and_then(|gh| gh.at(game.time)) {
+        let here = FineLoc::from_coords(at);
+        let n = lighting.own_lum(here.nearest());
+        if n >= DARK2_LIGHT {
+            let mut color = tint(lighting.rgb(here.nearest()), game.gamma);
+            color[3] = GHOST_ALPHA;
+            draw_tile_c((draw_cam - (map_cam - here)).as_coords(), Art::character(dir), color);
         }
     }
-    fn fine_loc(&self) -> FineLoc {
-        match self.loc {
-            Complete(l) => FineLoc::from_loc(l),
-            InProgress(r) => r.as_fineloc(),
+
+    // Draw character
+    let d_loc = draw_cam - (map_cam - game.fine_loc());
+    let d_coords = d_loc.as_coords();
+    draw_tile_c(d_coords, Art::character(game.dir), WHITE);
+
+    // Draw held keys next to the battery meter
+    for (i, color) in KeyColor::all().iter().enumerate() {
+        if game.inventory.contains(color) {
+            draw_tile_c((3.5 + i as f64, 0.0), Art::key(*color), WHITE);
         }
     }
-    fn settle_cam(&mut self) {
-        let d = self.fine_loc().sub(self.camera).as_coords();
-        if d.0 > CAM_DIST {
-            self.camera = self.fine_loc().sub(FineLoc::from_coords((CAM_DIST,0.0)))
-        } else if d.0 < -CAM_DIST {
-            self.camera = self.fine_loc().sub(FineLoc::from_coords((-CAM_DIST,0.0)))
-        } else if d.1 > CAM_DIST {
-            self.camera = self.fine_loc().sub(FineLoc::from_coords((0.0,CAM_DIST)))
-        } else if d.1 < CAM_DIST {
-            self.camera = self.fine_loc().sub(FineLoc::from_coords((0.0,-CAM_DIST)))
-        }
+
+    rectangle([1.0,1.0,1.0,0.5], [1.0,1.0,50.0,16.0], c.transform, g);
+    rectangle([0.0,0.0,0.0,1.0], [3.0,3.0,46.0,13.0], c.transform, g);
+    rectangle([1.0,1.0,1.0,0.5], [5.0,5.0,42.0 * game.battery.level(),8.0], c.transform, g);
+
+    // Show the beam setting under the battery meter: a long thin
+    // bar for narrow, a short fat one for wide, and just an
+    // outline when the flashlight is off
+    let beam = match game.beam {
+        Beam::Narrow => [5.0,21.0,28.0,3.0],
+        Beam::Wide => [5.0,19.0,16.0,7.0],
+    };
+    if game.light_on {
+        rectangle([1.0,0.92,0.75,0.8], beam, c.transform, g);
+    } else {
+        Rectangle::new_border([0.5,0.5,0.5,0.8], 0.5).draw(beam, &DrawState::default(), c.transform, g);
+    }
+
+    // The clock, under the battery and beam
+    font::draw_text(&clock(game.time), 5.0, 30.0, 1.0, WHITE, c, g);
+
+    if game.show_minimap {
+        draw_minimap(game, c, g);
     }
 }
 
-fn render<E>(game: &Game, window: &mut PistonWindow, e: E, tilesheet: &Texture<gfx_device_gl::Resources>) where E: piston_window::GenericEvent {
-    let mut lums = HashMap::new();
-    illuminate(
-        &game.maze,
-        &Source::mk_source(game.base_loc(), game.dir, game.battery),
-        &mut lums
-    );
-    match game.loc {
-        // If we are moving, perform a second illumination from
-        // the point of view of our destination and combine the
-        // two light-maps.
-        InProgress(r) => {
-            for lum in lums.values_mut() {
-                *lum = *lum * (1.0 - r.get_progress());
-            }
+/// Which level of the campaign is being played
+fn level_name(game: &Game) -> String {
+    format!("Level {}: {}", game.level + 1, game.pack.levels[game.level].name)
+}
 
-            let mut lums2 = HashMap::new();
-            illuminate(
-                &game.maze,
-                &Source::mk_source(r.dest(), game.dir, game.battery),
-                &mut lums2
-            );
-            for lum in lums2.values_mut() {
-                *lum = *lum * r.get_progress();
-            }
+/// Show a number of seconds as minutes and seconds
+fn clock(time: f64) -> String {
+    let secs = time as u64;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
 
-            for loc in lums2.keys() {
-                if let Some(lum) = lums.get(loc) {
-                    let lum1 = *lum;
-                    lums.insert(*loc, lum1 + lums2[loc]);
-                } else {
-                    lums.insert(*loc, lums2[loc]);
-                }
+/// Lines of stats for the end of a level, with a score for a win
+fn stats(game: &Game) -> Vec<String> {
+    let run = game.run();
+    let mut lines = vec![level_name(game)];
+    match game.par {
+        Some(par) => {
+            lines.push(format!("Time: {} (par {})", clock(run.time), clock(par.time)));
+            lines.push(format!("Tiles walked: {} (par {})", run.tiles, par.tiles));
+        },
+        None => {
+            lines.push(format!("Time: {}", clock(run.time)));
+            lines.push(format!("Tiles walked: {}", run.tiles));
+        },
+    }
+    lines.push(format!("Battery left: {:.0}%", run.battery * 100.0));
+    lines.push(format!("Explored: {:.0}%", run.explored * 100.0));
+    if let (State::Won, Some(par)) = (game.state, game.par) {
+        lines.push(format!("Score: {}", run.score(&par)));
+    }
+    lines
+}
+
+/// Lines listing the best runs through the current level, marking
+/// the one just finished if it made it in at place
+fn best_runs(game: &Game, scores: &HighScores, place: Option<usize>) -> Vec<String> {
+    let table = scores.table(&game.pack.score_key(game.level));
+    let mut lines = vec!["Best runs".to_string()];
+    for (i, entry) in table.iter().take(SCORES_SHOWN).enumerate() {
+        let new = if place == Some(i) { " - new!" } else { "" };
+        lines.push(format!("{}. {}  {}{}", i + 1, entry.score, clock(entry.time), new));
+    }
+    lines
+}
+
+/// Draw the title, pause, victory or game over screen, with its menu
+fn draw_screen(game: &Game, scores: &HighScores, place: Option<usize>, c: &Context, g: &mut G2d) {
+    let won = || [stats(game), best_runs(game, scores, place)].concat();
+    let (heading, scale, lines) = match game.state {
+        State::Title => ("Eye of the Pharaohs", 4.0, match &game.pack.name {
+            Some(name) => vec![name.clone()],
+            None => vec![
+                "Find the treasure before".to_string(),
+                "your flashlight dies.".to_string(),
+            ],
+        }),
+        State::Paused => ("Paused", 3.0, vec![level_name(game)]),
+        State::Won if game.last_level() => ("You found the Eye!", 3.0, won()),
+        State::Won => ("Level complete", 3.0, won()),
+        State::Lost => ("Your light went out", 3.0, stats(game)),
+        State::Playing => {
+            // Say which level this is for a moment after it starts
+            if game.time < LEVEL_BANNER {
+                let y = c.get_view_size()[1] - SCREEN_TOP;
+                font::draw_centered(&level_name(game), y, 2.0, [1.0,0.92,0.75,1.0], c, g);
             }
+            return;
+        },
+        State::Quit => return,
+    };
+    let [w, h] = c.get_view_size();
+    if game.state != State::Title {
+        // Shade the maze over so the text stands out
+        rectangle([0.0,0.0,0.0,0.7], [0.0, 0.0, w, h], c.transform, g);
+    }
+
+    let text = [1.0,0.92,0.75,1.0];
+    let dim = [0.6,0.6,0.6,1.0];
+    let mut y = SCREEN_TOP;
+    font::draw_centered(heading, y, scale, text, c, g);
+    y += font::text_height(scale) * 2.0;
+    // Squeeze long lists of lines closer together to fit the menu in
+    let spacing = if lines.len() > 6 { 1.5 } else { 2.0 };
+    for line in lines.iter() {
+        font::draw_centered(line, y, 2.0, dim, c, g);
+        y += font::text_height(2.0) * spacing;
+    }
+
+    y += font::text_height(2.0);
+    for (i, choice) in game.menu().iter().enumerate() {
+        let label = choice.label();
+        if i == game.selected {
+            font::draw_centered(label, y, 2.0, text, c, g);
+            let left = (w - font::text_width(label, 2.0)) / 2.0;
+            font::draw_text(">", (left - font::text_width("> ", 2.0)).round(), y, 2.0, text, c, g);
+        } else {
+            font::draw_centered(label, y, 2.0, dim, c, g);
         }
-        _ => (),
+        y += font::text_height(2.0) * 2.0;
     }
+}
 
-    window.draw_2d(&e, |c, g, _| {
-        clear([0.0; 4], g);
+/// Draw the explored part of the maze, shrunk down, in the top right
+/// corner of the window
+fn draw_minimap(game: &Game, c: &Context, g: &mut G2d) {
+    let (lo, hi) = game.maze.bounds();
+    // Leave room for the walls around the edge
+    let lo = lo - Loc{x: 1, y: 1};
+    let size = hi - lo + Loc{x: 2, y: 2};
+    let width = size.x as f64 * MINIMAP_SCALE;
+    let left = c.get_view_size()[0] - width - MINIMAP_MARGIN;
+    let top = MINIMAP_MARGIN;
+    let square = |loc: Loc, inset: f64| {
+        let at = (loc - lo).as_coords();
+        [
+            left + at.0 * MINIMAP_SCALE + inset,
+            top + at.1 * MINIMAP_SCALE + inset,
+            MINIMAP_SCALE - inset * 2.0,
+            MINIMAP_SCALE - inset * 2.0,
+        ]
+    };
 
-        let mut draw_tile_c = |cs: (f64,f64), a: Art| {
-            let t = c.transform.trans(
-                ART_SIZE as f64 * cs.0,
-                ART_SIZE as f64 * cs.1,
-            );
-            a.image().draw(tilesheet, &DrawState::default(), t, g);
+    rectangle([0.0,0.0,0.0,0.6], [left, top, width, size.y as f64 * MINIMAP_SCALE], c.transform, g);
+    for loc in game.explored.iter() {
+        let color = match game.maze.map.get(loc) {
+            Some(Tile::Door(_)) => [0.8,0.3,0.3,0.9],
+            Some(_) => [0.7,0.7,0.6,0.9],
+            None => [0.3,0.3,0.3,0.9],
         };
+        rectangle(color, square(*loc, 0.0), c.transform, g);
+    }
+    if game.minimap_goal || game.explored.contains(&game.maze.goal) {
+        rectangle([1.0,0.9,0.0,1.0], square(game.maze.goal, 0.0), c.transform, g);
+    }
 
-        let mut draw_tile = |l: FineLoc, a: Art| {
-            draw_tile_c(l.as_coords(), a);
-        };
+    // The player, with a tick on the side they are facing
+    let here = game.base_loc();
+    rectangle([1.0,0.2,0.2,1.0], square(here, 0.0), c.transform, g);
+    let (dx, dy) = game.dir.offset();
+    let tick = square(here, MINIMAP_SCALE / 4.0);
+    rectangle(
+        [1.0,1.0,1.0,1.0],
+        [tick[0] + dx as f64 * MINIMAP_SCALE / 2.0, tick[1] + dy as f64 * MINIMAP_SCALE / 2.0, tick[2], tick[3]],
+        c.transform,
+        g,
+    );
+}
 
-        // Draw map tiles
-        let draw_cam = FineLoc::from_loc(Loc{ x: DRAW_DIST, y: DRAW_DIST });
-        let map_cam = game.camera.clone();
-
-        for x in 0..(DRAW_DIST * 2 + 1) {
-            for y in 0..(DRAW_DIST * 2 + 1) {
-                // The point on the screen we are filling in
-                let draw_loc = FineLoc::from_loc(Loc{x,y}).sub(FineLoc::from_coords(map_cam.get_offsets()));
-                // The location in the map we are representing
-                let map_loc = map_cam.sub(draw_cam.sub(draw_loc));
-                match (game.maze.map.get(&map_loc.base), lums.get(&map_loc.base)) {
-                    (Some(Tile::Floor), Some(n)) => {
-                        if !(*n < DARK2_LIGHT) {
-                            draw_tile(draw_loc, Art::Floor);
-                            if game.maze.goal == map_loc.base {
-                                draw_tile(draw_loc, Art::Goal);
-                            }
-                            if *n < DARK1_LIGHT {
-                                draw_tile(draw_loc, Art::Dark1);
-                            }
-                        }
-                    },
-                    (None, Some(n)) => {
-                        if !(*n < DARK2_LIGHT) {
-                            draw_tile(draw_loc, Art::Wall);
-                            if *n < DARK1_LIGHT {
-                                draw_tile(draw_loc, Art::Dark1);
-                            }
-                        }
-                    },
-                    _ => draw_tile(draw_loc, Art::Dark2),
-                }
+/// Add a win to the high scores, and keep it as the level's ghost if
+/// it's the best yet.  Gives its place in the level's table if it
+/// made it in.
+fn record_win(
+    game: &Game,
+    scores: &mut HighScores,
+    ghosts: &mut Ghosts,
+    keep_scores: bool,
+    keep_ghosts: bool,
+) -> Option<usize> {
+    let par = game.par?;
+    let entry = Entry::new(&game.run(), &par);
+    let place = scores.record(&game.pack.score_key(game.level), entry);
+    if keep_scores && place.is_some() {
+        if let Err(e) = scores.save() {
+            println!("Couldn't save high scores to {}: {}", HighScores::path().display(), e);
+        }
+    }
+    if let Some(key) = game.ghost_key() {
+        if ghosts.offer(&key, Ghost::new(entry.score, entry.time, &game.trace)) && keep_ghosts {
+            if let Err(e) = ghosts.save() {
+                println!("Couldn't save the ghost to {}: {}", Ghosts::path().display(), e);
             }
         }
+    }
+    place
+}
 
-        // Draw character
-        let d_loc = draw_cam.sub(map_cam.sub(game.fine_loc()));
-        let d_coords = d_loc.as_coords();
-        draw_tile_c(d_coords, game.c_art());
+/// Print every level's high scores
+fn list_scores() {
+    let scores = match HighScores::load() {
+        Ok(s) => s,
+        Err(e) => {
+            println!("Couldn't read {}: {}", HighScores::path().display(), e);
+            return;
+        },
+    };
+    let mut any = false;
+    for (level, table) in scores.levels() {
+        any = true;
+        println!("{}", level);
+        for (i, entry) in table.iter().enumerate() {
+            println!(
+                "  {:2}. {:5}  {:>5}  {:4} tiles  {:3.0}% battery",
+                i + 1, entry.score, clock(entry.time), entry.tiles, entry.battery * 100.0,
+            );
+        }
+    }
+    if !any {
+        println!("No high scores yet.");
+    }
+}
 
-        rectangle([1.0,1.0,1.0,0.5], [1.0,1.0,50.0,16.0], c.transform, g);
-        rectangle([0.0,0.0,0.0,1.0], [3.0,3.0,46.0,13.0], c.transform, g);
-        rectangle([1.0,1.0,1.0,0.5], [5.0,5.0,42.0 * ((game.battery - 5.0) / 95.0),8.0], c.transform, g);
+/// Read a recording along with the pack it was played in
+fn open_replay(fname: &str) -> Option<(Replay, Pack)> {
+    let replay = match Replay::load(fname) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("Couldn't read {}: {}", fname, e);
+            return None;
+        },
+    };
+    let pack = match &replay.pack {
+        Some(f) => match Pack::load(f) {
+            Ok(p) => p,
+            Err(e) => {
+                println!("Couldn't read {}: {}", f, e);
+                return None;
+            },
+        },
+        None => Pack::default(),
+    };
+    Some((replay, pack))
+}
 
+/// Play a recording through without showing it, and say how it went
+fn verify(fname: &str) {
+    let (replay, pack) = match open_replay(fname) {
+        Some(r) => r,
+        None => return,
+    };
+    let game = replay.play(pack, |game| {
+        for line in stats(game) {
+            println!("{}", line);
+        }
+        println!();
     });
+    println!("Played {} after {} steps.", level_name(&game), game.steps);
+}
+
+/// Read the pack file, or the usual pack if none is given, along
+/// with the file it came from
+fn open_pack(pack_file: Option<&str>) -> Option<(Pack, Option<String>)> {
+    // Without a pack file, there's just the one generated maze.  A
+    // pack asked for by name has to be there, though.
+    let fname = pack_file.unwrap_or(PACK_FILE);
+    match Pack::load(fname) {
+        Ok(p) => Some((p, Some(fname.to_string()))),
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound && pack_file.is_none() => {
+            Some((Pack::default(), None))
+        },
+        Err(e) => {
+            println!("Couldn't read {}: {}", fname, e);
+            None
+        },
+    }
+}
+
+/// Start a game of the pack file, or the usual pack if none is
+/// given, and start recording it
+fn new_game(pack_file: Option<String>) -> Option<(Game, Replay)> {
+    let (pack, pack_from) = open_pack(pack_file.as_deref())?;
+
+    // Kept small enough to fit in a TOML integer, for the recording
+    let seed = rand::random::<u64>() >> 1;
+    let mut game = Game::new(pack, seed);
+
+    // A level left partway through last time can be carried on from
+    // the title screen, as long as it's from this pack
+    match Save::load() {
+        Ok(Some(save)) => match save.check(pack_from.as_deref(), game.pack.levels.len()) {
+            Ok(()) => game.saved = Some(save),
+            Err(e) => println!("Not offering to continue the saved game: {}", e),
+        },
+        Ok(None) => (),
+        Err(e) => println!("Couldn't read {}: {}", Save::path().display(), e),
+    }
+
+    let recording = Replay::new(pack_from, seed, &game);
+    Some((game, recording))
 }
 
 fn main() {
-    let mut game: Game = Game::new(maze_gen::generate(20));
+    let args: Vec<String> = std::env::args().collect();
+    // Something other than a person at the controls may be playing:
+    // a recording being watched, or a bot showing the game off
+    let (pack_file, watching): (_, Option<(Game, Box<dyn Controller>)>) = match args.get(1).map(|a| a.as_str()) {
+        Some("bots") => {
+            if let Some((pack, _)) = open_pack(args.get(2).map(|a| a.as_str())) {
+                bench::bots(&pack);
+            }
+            return;
+        },
+        Some("demo") => {
+            let name = args.get(2).map(|a| a.as_str()).unwrap_or("astar");
+            let bot = match bots::bot(name) {
+                Some(b) => b,
+                None => {
+                    println!("There's no {} bot.  Try one of: {}", name, bots::BOT_NAMES.join(", "));
+                    return;
+                },
+            };
+            match open_pack(args.get(3).map(|a| a.as_str())) {
+                Some((pack, _)) => (None, Some((Game::new(pack, rand::random()), bot))),
+                None => return,
+            }
+        },
+        Some("scores") => {
+            list_scores();
+            return;
+        },
+        Some(cmd @ "verify") | Some(cmd @ "replay") => match args.get(2) {
+            Some(f) if cmd == "verify" => {
+                verify(f);
+                return;
+            },
+            Some(f) => match open_replay(f) {
+                Some((replay, pack)) => (None, Some((replay.game(pack), Box::new(Player::new(replay))))),
+                None => return,
+            },
+            None => {
+                println!("{} needs the replay file to play back.", cmd);
+                return;
+            },
+        },
+        Some("--pack") => match args.get(2) {
+            Some(f) => (Some(f.clone()), None),
+            None => {
+                println!("--pack needs the pack file to play.");
+                return;
+            },
+        },
+        _ => (None, None),
+    };
+
+    // Bindings come from controls.toml when there is one
+    let mut controls = match Controls::load(CONTROLS_FILE) {
+        Ok(c) => c,
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Controls::default(),
+        Err(e) => {
+            println!("Couldn't read {}: {}", CONTROLS_FILE, e);
+            println!("Using the default controls.");
+            Controls::default()
+        },
+    };
+
+    // Scores that can't be read aren't saved over, so that they
+    // aren't lost
+    let (mut scores, keep_scores) = match HighScores::load() {
+        Ok(s) => (s, true),
+        Err(e) => {
+            println!("Couldn't read {}: {}", HighScores::path().display(), e);
+            println!("High scores won't be saved.");
+            (HighScores::default(), false)
+        },
+    };
+    // Where the last win placed in its level's table
+    let mut place = None;
+    let (mut ghosts, keep_ghosts) = match Ghosts::load() {
+        Ok(g) => (g, true),
+        Err(e) => {
+            println!("Couldn't read {}: {}", Ghosts::path().display(), e);
+            println!("Ghosts won't be saved.");
+            (Ghosts::default(), false)
+        },
+    };
+
+    // Every game a person plays is recorded
+    let (mut game, mut recording, mut controller) = match watching {
+        Some((game, controller)) => (game, None, controller),
+        None => match new_game(pack_file) {
+            Some((game, recording)) => (game, Some(recording), Box::new(Keyboard::new()) as Box<dyn Controller>),
+            None => return,
+        },
+    };
+    let had_save = game.saved.is_some();
+    // Play time not yet taken up by a step
+    let mut lag = 0.0;
 
     let mut window: PistonWindow = 
         WindowSettings::new("Hello Piston!", [640, 480])
-        .exit_on_esc(true).build().unwrap();
+        .build().unwrap();
 
     let mut texture_context = TextureContext {
         factory: window.factory.clone(),
@@ -289,42 +657,62 @@ fn main() {
 
     while let Some(e) = window.next() {
         if let Some(args) = e.update_args() {
-            game.battery -= args.dt * 4.0;
-            game.update(args.dt);
-        }
-        if game.battery <= 5.0 {
-            println!("You died.");
-            break;
-        }
-        match e.press_args() {
-            Some(Button::Keyboard(k)) => match k {
-                Key::W => game.intend(Dir::north()),
-                Key::S => game.intend(Dir::south()),
-                Key::A => game.intend(Dir::west()),
-                Key::D => game.intend(Dir::east()),
-                _ => (),
+            lag += args.dt;
+            while lag >= STEP {
+                lag -= STEP;
+                if controller.done(&game) {
+                    // Nothing more will happen, so hold still on the
+                    // last moment
+                    lag = 0.0;
+                    break;
+                }
+                for (action, state) in controller.actions(&game) {
+                    game.act(action, state);
+                    if let Some(r) = &mut recording {
+                        r.record(&game, action, state);
+                    }
+                }
+                let was = game.state;
+                game.step();
+                if game.state == State::Won && was != State::Won && recording.is_some() {
+                    place = record_win(&game, &mut scores, &mut ghosts, keep_scores, keep_ghosts);
+                }
             }
-            _ => (),
         }
-        match e.release_args() {
-            Some(Button::Keyboard(k)) => match k {
-                Key::W => game.unintend(Dir::north()),
-                Key::S => game.unintend(Dir::south()),
-                Key::A => game.unintend(Dir::west()),
-                Key::D => game.unintend(Dir::east()),
-                _ => (),
-            }
-            _ => (),
+        let actions = controls.actions(&e);
+        // Back stops watching
+        if recording.is_none() && actions.contains(&(Action::Back, ButtonState::Press)) {
+            return;
+        }
+        controller.input(&actions);
+        if game.state == State::Quit && recording.is_some() {
+            break;
         }
 
-        if let Some(args) = e.render_args() {
-            game.settle_cam();
-            render(&game, &mut window, e, &tilesheet);
+        if e.render_args().is_some() {
+            // Race the best run through this level, if it has one
+            let ghost = game.ghost_key().and_then(|k| ghosts.get(&k));
+            render(&game, ghost, &scores, place, &mut window, e, &tilesheet);
         }
+    }
+    let mut recording = match recording {
+        Some(r) => r,
+        None => return,
+    };
+    recording.finish(&game);
+    if let Err(e) = recording.save() {
+        println!("Couldn't record the game in {}: {}", Replay::dir().display(), e);
+    }
 
-        if game.base_loc() == game.maze.goal {
-            println!("You found the Eye of the Pharaohs.");
-            break;
+    // Save a level that was left partway through, and otherwise drop
+    // a save once it has been carried on from
+    if game.in_progress() {
+        if let Err(e) = Save::new(&game, recording.pack).save() {
+            println!("Couldn't save the game to {}: {}", Save::path().display(), e);
+        }
+    } else if had_save && game.saved.is_none() {
+        if let Err(e) = Save::remove() {
+            println!("Couldn't remove {}: {}", Save::path().display(), e);
         }
     }
 }
@@ -1,13 +1,91 @@
+use serde::{Deserialize, Serialize};
+
 use std::fs::File;
 use std::io::prelude::*;
-use std::collections::HashMap;
+use std::collections::{HashMap,HashSet};
 
 use crate::geometry::*;
 
+/// Colors of keys, and of the locked doors they open
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Hash, Serialize, Deserialize)]
+pub enum KeyColor {
+    Red,
+    Blue,
+    Yellow,
+}
+
+impl KeyColor {
+    /// All key colors, in the order the generator hands them out
+    pub fn all() -> [KeyColor; 3] {
+        [KeyColor::Red, KeyColor::Blue, KeyColor::Yellow]
+    }
+}
+
 /// A descriptor of the features of a maze location
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum Tile {
     Floor,
+    /// A key lying on the floor, picked up by walking onto it
+    Key(KeyColor),
+    /// A locked door, which can only be walked through while holding
+    /// the key of the same color
+    Door(KeyColor),
+    /// One of a numbered pair of teleporters.  Walking onto it moves
+    /// the player to the other teleporter with the same number.
+    Teleporter(u8),
+    /// Floor with a lamp standing on it, which lights up the area
+    /// around it no matter where the player is
+    Lamp,
+}
+
+impl Tile {
+    /// Can the tile be walked onto, given the keys currently held?
+    pub fn passable(self, keys: &HashSet<KeyColor>) -> bool {
+        match self {
+            Tile::Floor | Tile::Key(_) | Tile::Teleporter(_) | Tile::Lamp => true,
+            Tile::Door(c) => keys.contains(&c),
+        }
+    }
+    /// Does light pass through the tile?  Doors block light just like
+    /// walls, whether or not the player can open them.
+    pub fn transparent(self) -> bool {
+        match self {
+            Tile::Floor | Tile::Key(_) | Tile::Teleporter(_) | Tile::Lamp => true,
+            Tile::Door(_) => false,
+        }
+    }
+    /// The tile a character stands for in a maze's text, or None for
+    /// a wall.  Start and goal markers aren't tiles, so they aren't
+    /// handled here.
+    pub fn from_char(c: char) -> Option<Option<Tile>> {
+        match c {
+            '.' | ' ' => Some(Some(Tile::Floor)),
+            '=' => Some(None),
+            'r' => Some(Some(Tile::Key(KeyColor::Red))),
+            'b' => Some(Some(Tile::Key(KeyColor::Blue))),
+            'y' => Some(Some(Tile::Key(KeyColor::Yellow))),
+            'R' => Some(Some(Tile::Door(KeyColor::Red))),
+            'B' => Some(Some(Tile::Door(KeyColor::Blue))),
+            'Y' => Some(Some(Tile::Door(KeyColor::Yellow))),
+            '*' => Some(Some(Tile::Lamp)),
+            '0'..='9' => Some(Some(Tile::Teleporter(c as u8 - b'0'))),
+            _ => None,
+        }
+    }
+    /// The character that stands for the tile in a maze's text
+    pub fn to_char(self) -> char {
+        match self {
+            Tile::Floor => '.',
+            Tile::Key(KeyColor::Red) => 'r',
+            Tile::Key(KeyColor::Blue) => 'b',
+            Tile::Key(KeyColor::Yellow) => 'y',
+            Tile::Door(KeyColor::Red) => 'R',
+            Tile::Door(KeyColor::Blue) => 'B',
+            Tile::Door(KeyColor::Yellow) => 'Y',
+            Tile::Lamp => '*',
+            Tile::Teleporter(n) => (b'0' + n) as char,
+        }
+    }
 }
 
 /// A map of maze tiles, with start and goal positions.  A correctly
@@ -18,14 +96,69 @@ pub struct Maze {
     pub start: Loc,
     pub goal: Loc,
     pub map: HashMap<Loc,Tile>,
+    /// Each teleporter's partner, worked out once when the maze is
+    /// made.  Teleporters never move, so changes to the map after
+    /// that don't affect it.
+    teleports: HashMap<Loc,Loc>,
+}
+
+impl Maze {
+    pub fn new(start: Loc, goal: Loc, map: HashMap<Loc,Tile>) -> Maze {
+        let teleports = Maze::pair_teleporters(&map);
+        Maze{start, goal, map, teleports}
+    }
+    /// Map each teleporter to its partner.  A teleporter number must
+    /// appear exactly twice to make a pair; any others are left out
+    /// and behave like plain floor.
+    pub fn teleporters(&self) -> &HashMap<Loc,Loc> {
+        &self.teleports
+    }
+    fn pair_teleporters(map: &HashMap<Loc,Tile>) -> HashMap<Loc,Loc> {
+        let mut by_num: HashMap<u8,Vec<Loc>> = HashMap::new();
+        for (loc, t) in map.iter() {
+            if let Tile::Teleporter(n) = t {
+                by_num.entry(*n).or_default().push(*loc);
+            }
+        }
+        let mut pairs = HashMap::new();
+        for locs in by_num.values() {
+            if let [a, b] = locs[..] {
+                pairs.insert(a, b);
+                pairs.insert(b, a);
+            }
+        }
+        pairs
+    }
+    /// Smallest and largest corners of the rectangle holding every
+    /// open tile in the maze
+    pub fn bounds(&self) -> (Loc, Loc) {
+        let mut lo = self.start;
+        let mut hi = self.start;
+        for loc in self.map.keys() {
+            lo = Loc{x: lo.x.min(loc.x), y: lo.y.min(loc.y)};
+            hi = Loc{x: hi.x.max(loc.x), y: hi.y.max(loc.y)};
+        }
+        (lo, hi)
+    }
+    /// Locations of all the lamps in the maze
+    pub fn lamps(&self) -> Vec<Loc> {
+        self.map.iter()
+            .filter(|(_, t)| **t == Tile::Lamp)
+            .map(|(loc, _)| *loc)
+            .collect()
+    }
 }
 
 /// Parse a maze from a text file, in which '.' is a space, '=' is a
-/// wall, 's' is the starting point and 'g' is the goal point.
+/// wall, 's' is the starting point and 'g' is the goal point.  Keys
+/// are 'r', 'b' and 'y' (red, blue and yellow), and the doors they
+/// open are the matching capitals 'R', 'B' and 'Y'.  The digits '0'
+/// through '9' are teleporters, each digit marking a matched pair,
+/// and '*' is a lamp.
 ///
 /// There can only be one starting point and one goal.  If multiple
 /// 's' or 'g' chars appear in the text file, the last occurrence of
-/// each is used.
+/// each is used.  Any other character is an InvalidData error.
 pub fn parse_maze(fname: &str) -> std::io::Result<Maze> {
     let mut file = File::open(fname)?;
     let mut contents = String::new();
@@ -40,19 +173,23 @@ pub fn parse_maze(fname: &str) -> std::io::Result<Maze> {
     for c in contents.chars() {
         let loc: Loc = Loc{x,y};
         match c {
-            '.' => {map.insert(loc.clone(), Tile::Floor);},
-            ' ' => {map.insert(loc.clone(), Tile::Floor);},
-            '=' => (),
             's' => {
-                map.insert(loc.clone(), Tile::Floor);
-                start = loc.clone();
+                map.insert(loc, Tile::Floor);
+                start = loc;
             },
             'g' => {
-                map.insert(loc.clone(), Tile::Floor);
-                goal = loc.clone();
+                map.insert(loc, Tile::Floor);
+                goal = loc;
             },
             '\n' => brk = true,
-            _ => panic!("Don't know that char."),
+            _ => match Tile::from_char(c) {
+                Some(Some(t)) => {map.insert(loc, t);},
+                Some(None) => (),
+                None => return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("don't know the character {:?} on line {}", c, y + 1),
+                )),
+            },
         }
         if brk {
             brk = false;
@@ -62,7 +199,7 @@ pub fn parse_maze(fname: &str) -> std::io::Result<Maze> {
             x += 1;
         }
     }
-    Ok(Maze{start, goal, map})
+    Ok(Maze::new(start, goal, map))
 }
 
 /// The maze generation function re-uses the code from c-maze,
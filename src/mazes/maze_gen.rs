@@ -1,9 +1,15 @@
-use libc::{c_int, c_float};
+use libc::{c_int, c_uint, c_float};
 
-use std::collections::HashMap;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+
+use std::collections::{HashMap,HashSet};
 
 use crate::geometry::*;
-use super::{Maze,Tile};
+use crate::solver;
+use super::{KeyColor,Maze,Tile};
 
 
 #[repr(C)]
@@ -27,12 +33,13 @@ pub struct CMaze {
     pub size: c_int,
 }
 
-extern {
+extern "C" {
     fn generate_maze(
         size: c_int,
         twisty: c_int,
         swirly: c_int,
-        branchy: c_int
+        branchy: c_int,
+        seed: c_uint
     ) -> *mut CMaze;
 
     fn destroy_maze(maze: *mut CMaze);
@@ -59,25 +66,134 @@ unsafe fn translate_cmaze(cmaze: *const CMaze) -> Maze {
             //
             // See get_tiletype in c_src/internals.c
             let tile_ptr = (*cmaze).tiles.offset((x * size + y) as isize);
-            match (*tile_ptr).t {
-                1 => { map.insert(Loc{x: x as isize,y: y as isize}, Tile::Floor); },
-                _ => (),
+            if (*tile_ptr).t == 1 {
+                map.insert(Loc{x: x as isize,y: y as isize}, Tile::Floor);
             }
         }
     }
 
-    Maze{start, goal, map}
+    Maze::new(start, goal, map)
+}
+
+/// Settings for generating a maze.  The odds are percentages.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Params {
+    /// Length of one side of the maze
+    pub size: i32,
+    /// Odds of a passage changing direction as it is dug
+    pub twisty: i32,
+    /// Odds of turning right rather than left on changing direction
+    pub swirly: i32,
+    /// Odds of a passage branching off
+    pub branchy: i32,
+    /// Locked doors to place, each with its key
+    pub doors: usize,
+    /// Seed to always make the same maze from, or None for a
+    /// different maze every time.  The generator takes 32-bit seeds,
+    /// and leans on the C library's rand(), so a seed only makes the
+    /// same maze with the same C library.
+    pub seed: Option<u32>,
+}
+
+impl Default for Params {
+    /// The odds come from their defaults in c-maze, which were found
+    /// with a bit of trial-and-error to make decent mazes.
+    fn default() -> Params {
+        Params{size: 20, twisty: 70, swirly: 50, branchy: 30, doors: 0, seed: None}
+    }
+}
+
+impl Params {
+    /// Say what's wrong with the settings, if anything
+    pub fn check(&self) -> Result<(), String> {
+        if self.size < 10 {
+            return Err(format!("a generated maze's size must be at least 10, not {}", self.size));
+        }
+        for (name, odds) in [("twisty", self.twisty), ("swirly", self.swirly), ("branchy", self.branchy)].iter() {
+            if *odds < 0 || *odds > 100 {
+                return Err(format!("{} must be from 0 to 100, not {}", name, odds));
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Generate a random square-shaped maze.  The provided size will be
 /// the length of one side of the maze.
 pub fn generate(size: i32) -> Maze {
+    generate_plain(&Params{size, ..Params::default()}, rand::random())
+}
+
+fn generate_plain(params: &Params, seed: u32) -> Maze {
     unsafe {
-        // Parameters come from their defaults in c-maze, which were
-        // found with a bit of trial-and-error to make decent mazes.
-        let cmaze = generate_maze(size, 70, 50, 30);
+        let cmaze = generate_maze(params.size, params.twisty, params.swirly, params.branchy, seed as c_uint);
         let maze = translate_cmaze(cmaze);
         destroy_maze(cmaze); // free up tile array
         maze
     }
 }
+
+/// Generate a random maze and turn it into a lock-and-key puzzle.
+/// Up to `params.doors` locked doors are placed at chokepoints along
+/// the route to the goal, and each door's key is dropped somewhere
+/// that can be reached without passing through it.  The result is
+/// checked with the solver, falling back to the plain maze if it
+/// somehow can't be finished.
+pub fn generate_locked(params: &Params) -> Maze {
+    // The seed decides where the doors and keys go too
+    let seed = params.seed.unwrap_or_else(rand::random);
+    let maze = generate_plain(params, seed);
+    let mut locked = maze.clone();
+    add_locks(&mut locked, params.doors, &mut StdRng::seed_from_u64(u64::from(seed)));
+    if solver::solve(&locked).is_some() {
+        locked
+    } else {
+        maze
+    }
+}
+
+/// Place doors and their keys into a maze that has none yet.
+fn add_locks<R: Rng>(maze: &mut Maze, doors: usize, rng: &mut R) {
+    let route = match solver::solve(maze) {
+        Some(r) => r,
+        None => return,
+    };
+
+    // A door is only worth placing where it actually blocks the way
+    // to the goal.  Stay a few tiles clear of either end so that
+    // there is room to go looking for the key.
+    let none = HashSet::new();
+    let chokepoints: Vec<Loc> = route.iter().cloned()
+        .skip(3)
+        .take(route.len().saturating_sub(6))
+        .filter(|l| {
+            let mut blocked = maze.clone();
+            blocked.map.remove(l);
+            !solver::reachable(&blocked, maze.start, &none).contains(&maze.goal)
+        })
+        .collect();
+
+    let colors = KeyColor::all();
+    let count = doors.min(colors.len()).min(chokepoints.len());
+    let mut held = HashSet::new();
+    for (i, color) in colors.iter().take(count).enumerate() {
+        // Spread the doors out evenly along the route
+        let door = chokepoints[chokepoints.len() * (i + 1) / (count + 1)];
+        maze.map.insert(door, Tile::Door(*color));
+
+        // Everything reachable with the earlier keys, but without
+        // this one, is on the near side of the door.
+        let mut spots: Vec<Loc> = solver::reachable(maze, maze.start, &held)
+            .into_iter()
+            .filter(|l| *l != maze.start && maze.map.get(l) == Some(&Tile::Floor))
+            .collect();
+        // Put them in a fixed order, so that the same seed always
+        // picks the same spot
+        spots.sort_by_key(|l| (l.x, l.y));
+        if let Some(spot) = spots.choose(rng) {
+            maze.map.insert(*spot, Tile::Key(*color));
+        }
+        held.insert(*color);
+    }
+}
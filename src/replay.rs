@@ -0,0 +1,160 @@
+use piston_window::ButtonState;
+use serde::{Deserialize, Serialize};
+
+use std::convert::TryFrom;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::controls::*;
+use crate::controller::*;
+use crate::game::*;
+use crate::levels::*;
+use crate::save::*;
+
+/// An action's button going down or coming up, just before the game
+/// took the given step.  Written out as the step, then + for a press
+/// or - for a release, then the action: "120 +MoveEast".
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Event {
+    pub step: u64,
+    pub action: Action,
+    pub state: ButtonState,
+}
+
+impl TryFrom<String> for Event {
+    type Error = String;
+    fn try_from(s: String) -> Result<Event, String> {
+        let bad = || format!("bad replay event \"{}\"", s);
+        let mut words = s.split_whitespace();
+        let step = words.next().and_then(|w| w.parse().ok()).ok_or_else(bad)?;
+        let change = words.next().ok_or_else(bad)?;
+        let (state, name) = match (change.strip_prefix('+'), change.strip_prefix('-')) {
+            (Some(name), _) => (ButtonState::Press, name),
+            (_, Some(name)) => (ButtonState::Release, name),
+            _ => return Err(bad()),
+        };
+        let action = Action::deserialize(toml::Value::String(name.to_string())).map_err(|_| bad())?;
+        if words.next().is_some() {
+            return Err(bad());
+        }
+        Ok(Event{step, action, state})
+    }
+}
+
+impl From<Event> for String {
+    fn from(e: Event) -> String {
+        let change = match e.state {
+            ButtonState::Press => '+',
+            ButtonState::Release => '-',
+        };
+        format!("{} {}{:?}", e.step, change, e.action)
+    }
+}
+
+/// Everything needed to play a game over exactly as it went: the
+/// pack, the seed, any saved level on offer at the start, and every
+/// action taken along the way
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    /// The pack file played, or None for the single generated maze
+    pub pack: Option<String>,
+    pub seed: u64,
+    /// How many steps the game ran for
+    pub steps: u64,
+    pub events: Vec<Event>,
+    pub saved: Option<Save>,
+}
+
+impl Replay {
+    /// Where recordings are kept, one file for every game played
+    pub fn dir() -> PathBuf {
+        data_path("replays")
+    }
+    /// Start recording a game just made from the seed
+    pub fn new(pack: Option<String>, seed: u64, game: &Game) -> Replay {
+        Replay{pack, seed, steps: 0, events: Vec::new(), saved: game.saved.clone()}
+    }
+    /// Note an action taken in a game being recorded
+    pub fn record(&mut self, game: &Game, action: Action, state: ButtonState) {
+        self.events.push(Event{step: game.steps, action, state});
+    }
+    /// Stop recording, with the game as it is at the end
+    pub fn finish(&mut self, game: &Game) {
+        self.steps = game.steps;
+    }
+    /// Make the game as it was at the start of the recording
+    pub fn game(&self, pack: Pack) -> Game {
+        let mut game = Game::new(pack, self.seed);
+        game.saved = self.saved.clone();
+        game
+    }
+    pub fn load(fname: &str) -> io::Result<Replay> {
+        let contents = std::fs::read_to_string(fname)?;
+        toml::from_str(&contents).map_err(invalid)
+    }
+    /// Keep the recording alongside every other game played, in a
+    /// file named for when it was saved and the game's seed.  Gives
+    /// the file's path.
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let path = Self::dir().join(format!("{}-{}.toml", now, self.seed));
+        self.save_to(&path)?;
+        Ok(path)
+    }
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        write_toml(path, self)
+    }
+    /// Play the recording through without showing it, handing each
+    /// level to on_end as it is won or lost.  Gives the game as it
+    /// was when the recording ended.
+    pub fn play(self, pack: Pack, mut on_end: impl FnMut(&Game)) -> Game {
+        let mut game = self.game(pack);
+        let mut player = Player::new(self);
+        while !player.done(&game) {
+            for (action, state) in player.actions(&game) {
+                game.act(action, state);
+            }
+            let was = game.state;
+            game.step();
+            if game.state != was && (game.state == State::Won || game.state == State::Lost) {
+                on_end(&game);
+            }
+        }
+        game
+    }
+}
+
+/// Plays a recording back into a game, step by step
+pub struct Player {
+    replay: Replay,
+    /// The next event to hand to the game
+    next: usize,
+}
+
+impl Player {
+    pub fn new(replay: Replay) -> Player {
+        Player{replay, next: 0}
+    }
+}
+
+impl Controller for Player {
+    /// The actions taken just before the game's next step, when it
+    /// was recorded
+    fn actions(&mut self, game: &Game) -> Vec<(Action, ButtonState)> {
+        let mut actions = Vec::new();
+        while let Some(e) = self.replay.events.get(self.next) {
+            if e.step > game.steps {
+                break;
+            }
+            actions.push((e.action, e.state));
+            self.next += 1;
+        }
+        actions
+    }
+    /// The recording is over once the game has taken as many steps
+    fn done(&self, game: &Game) -> bool {
+        game.steps >= self.replay.steps || game.state == State::Quit
+    }
+}
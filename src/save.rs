@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::geometry::*;
+use crate::mazes::*;
+use crate::light::*;
+use crate::game::*;
+
+/// Where a file the game keeps between runs goes: in the user's data
+/// directory, or the current one if there isn't one
+pub fn data_path(fname: &str) -> PathBuf {
+    match dirs::data_dir() {
+        Some(dir) => dir.join("r-maze").join(fname),
+        None => PathBuf::from(fname),
+    }
+}
+
+/// An error for a file that was read but made no sense
+pub fn invalid<E>(e: E) -> io::Error where E: Into<Box<dyn std::error::Error + Send + Sync>> {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Read a TOML file, giving None if there isn't one
+pub fn read_toml<T: DeserializeOwned>(path: &Path) -> io::Result<Option<T>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents).map(Some).map_err(invalid),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Write a TOML file, making the directory it goes in if need be
+pub fn write_toml<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // Going through a Value puts plain values ahead of tables, as
+    // TOML needs
+    let value = toml::Value::try_from(value).map_err(invalid)?;
+    std::fs::write(path, toml::to_string(&value).map_err(invalid)?)
+}
+
+/// A maze written out as rows of text, in the same characters as a
+/// maze file.  The start and goal are kept apart from the rows, so
+/// that whatever lies under them is kept too.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MazeText {
+    pub start: Loc,
+    pub goal: Loc,
+    /// Location of the first character of the first row
+    pub origin: Loc,
+    pub rows: Vec<String>,
+}
+
+impl MazeText {
+    pub fn new(maze: &Maze) -> MazeText {
+        let (lo, hi) = maze.bounds();
+        let rows = (lo.y..=hi.y).map(|y| {
+            (lo.x..=hi.x).map(|x| match maze.map.get(&Loc{x, y}) {
+                Some(t) => t.to_char(),
+                None => '=',
+            }).collect()
+        }).collect();
+        MazeText{start: maze.start, goal: maze.goal, origin: lo, rows}
+    }
+    pub fn maze(&self) -> io::Result<Maze> {
+        let mut map = HashMap::new();
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let loc = self.origin + Loc{x: x as Int, y: y as Int};
+                match Tile::from_char(c) {
+                    Some(Some(t)) => {map.insert(loc, t);},
+                    Some(None) => (),
+                    None => return Err(invalid(format!("unknown maze character '{}'", c))),
+                }
+            }
+        }
+        Ok(Maze::new(self.start, self.goal, map))
+    }
+}
+
+/// A level left partway through, to be picked up again later
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Save {
+    /// The pack file the level is from, or None for the single
+    /// generated maze played without one
+    pub pack: Option<String>,
+    pub level: usize,
+    /// The maze as it is now, with keys picked up and so on
+    pub maze: MazeText,
+    /// The maze as it was at the start, for trying it again
+    pub fresh: MazeText,
+    /// The seed the maze was generated from, if it was
+    #[serde(default)]
+    pub maze_seed: Option<u32>,
+    pub loc: LocMode,
+    pub dir: Dir,
+    pub camera: FineLoc,
+    /// Battery charge, as a percentage
+    pub charge: f64,
+    pub light_on: bool,
+    pub beam: Beam,
+    pub inventory: Vec<KeyColor>,
+    pub explored: Vec<Loc>,
+    pub corners: Corners,
+    /// The loosest corner rules used so far, which saves from before
+    /// it was kept don't have
+    #[serde(default)]
+    pub loosest: Option<Corners>,
+    pub light_model: Model,
+    pub show_minimap: bool,
+    pub retro_shading: bool,
+    pub gamma: f64,
+    pub time: f64,
+    pub walked: usize,
+    #[serde(default)]
+    pub trace: Vec<(f64, f64)>,
+}
+
+impl Save {
+    /// Where the game is saved
+    pub fn path() -> PathBuf {
+        data_path("save.toml")
+    }
+    /// Save the level being played in a game of the given pack file
+    pub fn new(game: &Game, pack: Option<String>) -> Save {
+        Save{
+            pack,
+            level: game.level,
+            maze: MazeText::new(&game.maze),
+            fresh: MazeText::new(game.fresh()),
+            maze_seed: game.maze_seed,
+            loc: game.loc,
+            dir: game.dir,
+            camera: game.camera,
+            charge: game.battery.charge(),
+            light_on: game.light_on,
+            beam: game.beam,
+            inventory: game.inventory.iter().copied().collect(),
+            explored: game.explored.iter().copied().collect(),
+            corners: game.corners,
+            loosest: Some(game.loosest),
+            light_model: game.light_model,
+            show_minimap: game.show_minimap,
+            retro_shading: game.retro_shading,
+            gamma: game.gamma,
+            time: game.time,
+            walked: game.walked,
+            trace: game.trace.clone(),
+        }
+    }
+    /// Make sure the save is from the pack being played, and is in
+    /// good enough shape to carry on from
+    pub fn check(&self, pack: Option<&str>, levels: usize) -> io::Result<()> {
+        if self.pack.as_deref() != pack {
+            return Err(invalid("it is from a different pack"));
+        }
+        if self.level >= levels {
+            return Err(invalid(format!("the pack has no level {}", self.level + 1)));
+        }
+        self.maze.maze()?;
+        self.fresh.maze()?;
+        Ok(())
+    }
+    /// Read the saved game, if there is one
+    pub fn load() -> io::Result<Option<Save>> {
+        read_toml(&Self::path())
+    }
+    pub fn save(&self) -> io::Result<()> {
+        write_toml(&Self::path(), self)
+    }
+    /// Throw the saved game away, once it has been picked up again
+    pub fn remove() -> io::Result<()> {
+        match std::fs::remove_file(Self::path()) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            r => r,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+
+use crate::game::Corners;
+use crate::mazes::*;
+use crate::save::*;
+use crate::solver;
+
+/// How much longer than a perfect run par allows, since a real run
+/// has to feel its way through the dark
+const PAR_SLACK: f64 = 2.0;
+
+/// Points for beating par on time, walking no further than the
+/// shortest route, and finishing with a full battery
+const TIME_POINTS: f64 = 1000.0;
+const PATH_POINTS: f64 = 500.0;
+const BATTERY_POINTS: f64 = 500.0;
+
+/// Finishing faster than par earns more time points, up to this many
+/// times the usual amount
+const TIME_BONUS_LIMIT: f64 = 1.5;
+
+/// How many of the best runs each level keeps
+const TABLE_SIZE: usize = 10;
+
+/// What a good run through a level looks like
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Par {
+    /// Seconds to reach the goal
+    pub time: f64,
+    /// Tiles walked along the shortest route to the goal
+    pub tiles: usize,
+}
+
+impl Par {
+    /// Work out par for a maze from the shortest route through it
+    /// under the corner rules, walked at speed tiles per second.  A
+    /// par time set by hand, if there is one, is used over the worked
+    /// out one.  Gives None if the maze can't be solved.
+    pub fn new(maze: &Maze, speed: f64, time: Option<f64>, corners: Corners) -> Option<Par> {
+        let route = solver::solve_cornering(maze, corners)?;
+        let tiles = route.len() - 1;
+        // Diagonal steps are longer.  A step that lands somewhere
+        // further off went through a teleporter, and is counted as a
+        // straight one.
+        let length: f64 = route.windows(2).map(|w| {
+            match ((w[1].x - w[0].x).abs(), (w[1].y - w[0].y).abs()) {
+                (1, 1) => std::f64::consts::SQRT_2,
+                _ => 1.0,
+            }
+        }).sum();
+        let time = time.unwrap_or(length / speed * PAR_SLACK);
+        Some(Par{time, tiles})
+    }
+}
+
+/// How a run through a level went
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Run {
+    /// Seconds spent playing
+    pub time: f64,
+    pub tiles: usize,
+    /// How full the battery was at the end, from 0 to 1
+    pub battery: f64,
+    /// Fraction of the maze seen along the way
+    pub explored: f64,
+}
+
+impl Run {
+    /// Score a finished run against par.  Exploring isn't scored,
+    /// since wandering off the route costs time and tiles anyway.
+    pub fn score(&self, par: &Par) -> u32 {
+        let time = (par.time / self.time.max(0.001)).min(TIME_BONUS_LIMIT);
+        let path = (par.tiles as f64 / self.tiles.max(1) as f64).min(1.0);
+        let points = TIME_POINTS * time
+            + PATH_POINTS * path
+            + BATTERY_POINTS * self.battery.clamp(0.0, 1.0);
+        points.round() as u32
+    }
+}
+
+/// A run good enough to keep
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub score: u32,
+    pub time: f64,
+    pub tiles: usize,
+    pub battery: f64,
+}
+
+impl Entry {
+    pub fn new(run: &Run, par: &Par) -> Entry {
+        Entry{score: run.score(par), time: run.time, tiles: run.tiles, battery: run.battery}
+    }
+}
+
+/// The best runs through each level, kept between games.  Levels are
+/// told apart by a key from `Pack::score_key`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HighScores {
+    #[serde(default)]
+    level: BTreeMap<String, Vec<Entry>>,
+}
+
+impl HighScores {
+    /// Where high scores are kept
+    pub fn path() -> PathBuf {
+        data_path("scores.toml")
+    }
+    /// Read the high scores, starting afresh if none have been saved
+    pub fn load() -> io::Result<HighScores> {
+        Ok(read_toml(&Self::path())?.unwrap_or_default())
+    }
+    pub fn save(&self) -> io::Result<()> {
+        write_toml(&Self::path(), self)
+    }
+    /// Add a run to a level's table, best first.  Gives its place in
+    /// the table, or None if it didn't make it in.
+    pub fn record(&mut self, key: &str, entry: Entry) -> Option<usize> {
+        let table = self.level.entry(key.to_string()).or_default();
+        // Ties go to the quicker run, and then to the earlier one
+        let place = table.iter()
+            .position(|e| (entry.score, -entry.time) > (e.score, -e.time))
+            .unwrap_or(table.len());
+        if place >= TABLE_SIZE {
+            return None;
+        }
+        table.insert(place, entry);
+        table.truncate(TABLE_SIZE);
+        Some(place)
+    }
+    /// The best runs through a level, best first
+    pub fn table(&self, key: &str) -> &[Entry] {
+        self.level.get(key).map(|t| t.as_slice()).unwrap_or(&[])
+    }
+    /// Every level with a table, and its table
+    pub fn levels(&self) -> impl Iterator<Item = (&String, &Vec<Entry>)> {
+        self.level.iter()
+    }
+}
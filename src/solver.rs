@@ -0,0 +1,182 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap,HashMap,HashSet,VecDeque};
+use std::collections::hash_map::Entry;
+
+use crate::game::Corners;
+use crate::geometry::*;
+use crate::mazes::*;
+
+/// Directions the solver walks in.  Diagonal steps are left out,
+/// since unless corners can be cut, any diagonal step can be made
+/// as two straight ones.
+pub fn walk_dirs() -> [Dir; 4] {
+    [Dir::north(), Dir::east(), Dir::south(), Dir::west()]
+}
+
+/// Keys held at some point in a search, one bit per color
+type KeyBits = u8;
+
+fn key_bit(c: KeyColor) -> KeyBits {
+    1 << (c as u8)
+}
+
+fn key_bits(keys: &HashSet<KeyColor>) -> KeyBits {
+    keys.iter().fold(0, |bits, c| bits | key_bit(*c))
+}
+
+fn key_set(bits: KeyBits) -> HashSet<KeyColor> {
+    KeyColor::all().iter().cloned().filter(|c| bits & key_bit(*c) != 0).collect()
+}
+
+/// Where a search has got to: a location, and the keys held there
+type State = (Loc, KeyBits);
+
+/// Walk the parent links of a search back from where it ended to
+/// where it started, giving the locations along the way in order
+fn route_to(parents: &HashMap<State,State>, first: State, last: State) -> Vec<Loc> {
+    let mut route = vec![last.0];
+    let mut state = last;
+    while state != first {
+        state = parents[&state];
+        route.push(state.0);
+    }
+    route.reverse();
+    route
+}
+
+/// The places one step on from a search state, and the keys held on
+/// getting there.  Diagonal steps are taken only if there are corner
+/// rules to allow them.
+fn steps_from(maze: &Maze, (loc, bits): State, corners: Option<Corners>) -> Vec<State> {
+    let held = key_set(bits);
+    let open = |d: Dir| maze.map.get(&loc.adj(d)).is_some_and(|t| t.passable(&held));
+    let dirs: Vec<Dir> = match corners {
+        Some(c) => (0..DIR_RESOLUTION).map(Dir::from).filter(|d| c.allow(*d, open)).collect(),
+        None => walk_dirs().to_vec(),
+    };
+    dirs.iter().filter_map(|d| {
+        let next = loc.adj(*d);
+        match maze.map.get(&next) {
+            Some(t) if t.passable(&held) => {
+                let next_bits = match t {
+                    Tile::Key(c) => bits | key_bit(*c),
+                    _ => bits,
+                };
+                Some((*maze.teleporters().get(&next).unwrap_or(&next), next_bits))
+            },
+            _ => None,
+        }
+    }).collect()
+}
+
+/// Find a shortest walk from the maze's start to its goal, picking
+/// up whatever keys are needed along the way.  The route includes
+/// both the start and the goal.
+///
+/// Stepping onto a teleporter lands on its partner, so the route
+/// lists the partner and skips the teleporter that was stepped on.
+/// Each step in the route is then one tile walked.
+pub fn solve(maze: &Maze) -> Option<Vec<Loc>> {
+    solve_from(maze, maze.start, &HashSet::new(), None)
+}
+
+/// Find a walk from the maze's start to its goal that steps onto as
+/// few tiles as it can, taking diagonal steps where the corner rules
+/// allow them
+pub fn solve_cornering(maze: &Maze, corners: Corners) -> Option<Vec<Loc>> {
+    solve_from(maze, maze.start, &HashSet::new(), Some(corners))
+}
+
+/// Find a shortest walk from any location to the goal, starting out
+/// with the given keys in hand.  Without corner rules, only straight
+/// steps are taken.
+pub fn solve_from(maze: &Maze, from: Loc, keys: &HashSet<KeyColor>, corners: Option<Corners>) -> Option<Vec<Loc>> {
+    let first = (from, key_bits(keys));
+    let mut parents: HashMap<State,State> = HashMap::new();
+    let mut queue = VecDeque::new();
+    parents.insert(first, first);
+    queue.push_back(first);
+
+    while let Some(here) = queue.pop_front() {
+        if here.0 == maze.goal {
+            return Some(route_to(&parents, first, here));
+        }
+        for state in steps_from(maze, here, corners) {
+            if let Entry::Vacant(e) = parents.entry(state) {
+                e.insert(here);
+                queue.push_back(state);
+            }
+        }
+    }
+    None
+}
+
+/// Find a shortest walk to the goal just as `solve_from` does, but
+/// with an A* search that tries steps toward the goal first.  How far
+/// there is left to go is guessed as the distance to the goal, or to
+/// the nearest teleporter if that is closer, so that the guess is
+/// never too long.
+pub fn astar(maze: &Maze, from: Loc, keys: &HashSet<KeyColor>) -> Option<Vec<Loc>> {
+    let teleports = maze.teleporters();
+    let dist = |a: Loc, b: Loc| (a.x - b.x).abs() + (a.y - b.y).abs();
+    let guess = |l: Loc| teleports.keys()
+        .map(|t| dist(l, *t))
+        .fold(dist(l, maze.goal), Int::min);
+    let first = (from, key_bits(keys));
+    let mut parents: HashMap<State,State> = HashMap::new();
+    let mut costs: HashMap<State,Int> = HashMap::new();
+    // Locations go in as their coordinates, since Loc isn't ordered
+    let mut open = BinaryHeap::new();
+    parents.insert(first, first);
+    costs.insert(first, 0);
+    open.push(Reverse((guess(from), 0, from.x, from.y, first.1)));
+
+    while let Some(Reverse((_, cost, x, y, bits))) = open.pop() {
+        let here = (Loc{x, y}, bits);
+        if cost > costs[&here] {
+            // Already reached a shorter way
+            continue;
+        }
+        if here.0 == maze.goal {
+            return Some(route_to(&parents, first, here));
+        }
+        for state in steps_from(maze, here, None) {
+            let next_cost = cost + 1;
+            if costs.get(&state).is_none_or(|c| next_cost < *c) {
+                costs.insert(state, next_cost);
+                parents.insert(state, here);
+                let (loc, bits) = state;
+                open.push(Reverse((next_cost + guess(loc), next_cost, loc.x, loc.y, bits)));
+            }
+        }
+    }
+    None
+}
+
+/// Collect every location that can be walked to from the given one
+/// while holding exactly the given keys.  Keys lying on the floor
+/// are not picked up along the way.
+pub fn reachable(maze: &Maze, from: Loc, keys: &HashSet<KeyColor>) -> HashSet<Loc> {
+    let teleports = maze.teleporters();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+    seen.insert(from);
+    queue.push_back(from);
+    while let Some(loc) = queue.pop_front() {
+        for d in walk_dirs().iter() {
+            let next = loc.adj(*d);
+            match maze.map.get(&next) {
+                Some(t) if t.passable(keys) && !seen.contains(&next) => {
+                    seen.insert(next);
+                    // Teleporters lead on from their partner, which
+                    // counts as reached too
+                    let land = *teleports.get(&next).unwrap_or(&next);
+                    seen.insert(land);
+                    queue.push_back(land);
+                },
+                _ => (),
+            }
+        }
+    }
+    seen
+}
@@ -0,0 +1,61 @@
+use r_maze::game::*;
+use r_maze::levels::*;
+use r_maze::light::Beam;
+use r_maze::solver;
+
+/// Seeds to try each level with.  Levels with a maze of their own
+/// come out the same every time, but the rest get a new one each.
+const SEEDS: u64 = 100;
+
+/// Check that every level in the shipped pack can be won: walking the
+/// shortest way to the goal with the flashlight on must not run the
+/// battery flat.
+#[test]
+fn shipped_levels_can_be_won() {
+    let pack = Pack::load("levels.toml").unwrap();
+    let speed = Game::new(pack.clone(), 0).speed;
+    for level in &pack.levels {
+        let config = level.battery_config();
+        let charge = config.start - config.empty;
+        let walkable = charge / (config.drain_moving * Beam::Narrow.load()) * speed;
+        for seed in 0..SEEDS {
+            let maze = level.maze(seed);
+            let route = solver::solve(&maze).unwrap();
+            let length: f64 = route.windows(2).map(|w| {
+                let (dx, dy) = (w[1].x - w[0].x, w[1].y - w[0].y);
+                ((dx * dx + dy * dy) as f64).sqrt()
+            }).sum();
+            assert!(
+                length <= walkable,
+                "level \"{}\" with seed {} is {:.0} tiles to the goal, but the battery only lasts {:.0}",
+                level.name, seed, length, walkable,
+            );
+        }
+    }
+}
+
+/// Check that a pack with a level set up so it can't work is turned
+/// away when it's loaded, rather than partway through the campaign.
+#[test]
+fn broken_levels_are_rejected() {
+    let broken = [
+        "generate = { size = 5 }",
+        "generate = { twisty = 101 }",
+        "generate = { branchy = -1 }",
+        "generate = {}\nbattery = 0",
+        "generate = {}\nbattery = 120",
+        "generate = {}\ndrain = 0",
+        "generate = {}\npar = -10",
+        "generate = {}\ncurve = 0",
+        "generate = {}\nflicker_below = 150",
+        "generate = {}\nbattery = 50\nempty = 50",
+    ];
+    let path = std::env::temp_dir().join(format!("r-maze-pack-{}.toml", std::process::id()));
+    std::fs::write(&path, "[[level]]\nname = \"Fine\"\ngenerate = {}\n").unwrap();
+    assert!(Pack::load(&path.to_string_lossy()).is_ok());
+    for level in broken.iter() {
+        std::fs::write(&path, format!("[[level]]\nname = \"Broken\"\n{}\n", level)).unwrap();
+        assert!(Pack::load(&path.to_string_lossy()).is_err(), "loaded a level with {}", level);
+    }
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,51 @@
+use r_maze::bots;
+use r_maze::game::*;
+use r_maze::levels::*;
+use r_maze::replay::*;
+
+/// Seed the recorded game is played with
+const SEED: u64 = 7;
+
+/// Steps to record, enough for the A* bot to get through a few levels
+const STEPS: u64 = 3000;
+
+/// Have a bot play the shipped pack while recording it, write the
+/// recording out and read it back, then check that playing it
+/// through ends up exactly where the live game did.
+#[test]
+fn replay_matches_live_game() {
+    let pack = Pack::load("levels.toml").unwrap();
+    let mut game = Game::new(pack.clone(), SEED);
+    let mut recording = Replay::new(Some("levels.toml".to_string()), SEED, &game);
+    let mut bot = bots::bot("astar").unwrap();
+    let mut live_ends = Vec::new();
+    while game.steps < STEPS {
+        for (action, state) in bot.actions(&game) {
+            game.act(action, state);
+            recording.record(&game, action, state);
+        }
+        let was = game.state;
+        game.step();
+        if game.state != was && (game.state == State::Won || game.state == State::Lost) {
+            live_ends.push((game.level, game.state, game.steps, game.walked));
+        }
+    }
+    recording.finish(&game);
+    assert!(live_ends.len() > 1, "the bot should finish more than one level");
+
+    let path = std::env::temp_dir().join(format!("r-maze-replay-{}.toml", std::process::id()));
+    recording.save_to(&path).unwrap();
+    let loaded = Replay::load(&path.to_string_lossy()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut played_ends = Vec::new();
+    let played = loaded.play(pack, |g| played_ends.push((g.level, g.state, g.steps, g.walked)));
+    assert_eq!(played_ends, live_ends);
+    assert_eq!(played.steps, game.steps);
+    assert_eq!(played.level, game.level);
+    assert_eq!(played.state, game.state);
+    assert_eq!(played.loc, game.loc);
+    assert_eq!(played.walked, game.walked);
+    assert_eq!(played.time, game.time);
+    assert_eq!(played.battery.charge(), game.battery.charge());
+}